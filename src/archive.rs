@@ -0,0 +1,211 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::cache::cache_dir;
+use crate::config_man::get_host_auth;
+use crate::git::{self, Revision};
+use crate::network;
+use crate::vendor;
+
+const ARCHIVE_EXTENSIONS: [&str; 6] = [".tar.gz", ".tgz", ".tar.bz2", ".tar.xz", ".tar", ".zip"];
+
+/// Whether `source` is a tarball or zip archive, local or remote.
+pub fn is_archive(source: &str) -> bool {
+    extension(source).is_some()
+}
+
+fn extension(source: &str) -> Option<&'static str> {
+    let path = source.split(['?', '#']).next().unwrap_or(source).to_lowercase();
+    ARCHIVE_EXTENSIONS.into_iter().find(|ext| path.ends_with(ext))
+}
+
+/// Archives live in the cache by content hash:
+///
+/// ```text
+/// <cache>/archives/<sha256>.<ext>   the downloaded archive
+/// <cache>/archives/<sha256>/        its unpacked contents
+/// ```
+fn archives_dir() -> Result<PathBuf> {
+    Ok(cache_dir()?.join("archives"))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn is_sha256(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn download(source: &str) -> Result<Vec<u8>> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        network::ensure_online(&format!("Downloading '{}'", source))?;
+        let url = git::rewrite(source);
+        let token = git::host(&url).and_then(|host| get_host_auth(host).token);
+        // Commands run inside the async runtime; the blocking client needs a thread it may block.
+        tokio::task::block_in_place(|| {
+            let mut request = network::blocking_client()?.get(&url);
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            let response = network::send_blocking(request)?;
+            if matches!(response.status().as_u16(), 401 | 403) {
+                let host = git::host(&url).unwrap_or(&url);
+                anyhow::bail!("authentication required for {}. Add an access token with `vpm config --token {}=<TOKEN>`.", host, host);
+            }
+            Ok(response.error_for_status()?.bytes()?.to_vec())
+        })
+        .with_context(|| format!("Failed to download archive '{}'", source))
+    } else {
+        let path = source.strip_prefix("file://").unwrap_or(source);
+        fs::read(path).with_context(|| format!("Failed to read archive '{}'", path))
+    }
+}
+
+/// Downloads `source` into the cache unless an archive with `expected` hash is
+/// already there, and returns the path of the cached archive with its hash.
+/// Every archive read from the cache is hashed again, so a tampered cache is
+/// caught just like a changed upstream file.
+fn fetch(source: &str, expected: Option<&str>) -> Result<(PathBuf, String)> {
+    let ext = extension(source).unwrap_or(".tar");
+    let dir = archives_dir()?;
+    if let Some(expected) = expected {
+        let cached = dir.join(format!("{}{}", expected, ext));
+        if let Ok(bytes) = fs::read(&cached) {
+            if sha256_hex(&bytes) == expected {
+                return Ok((cached, expected.to_string()));
+            }
+            fs::remove_file(&cached)?;
+        }
+    }
+
+    let bytes = download(source)?;
+    let actual = sha256_hex(&bytes);
+    if let Some(expected) = expected {
+        anyhow::ensure!(
+            actual == expected,
+            "Checksum mismatch for archive '{}': vpm.toml has sha256 {}, the archive has {}",
+            source, expected, actual
+        );
+    }
+    store(source, &bytes).map(|path| (path, actual))
+}
+
+/// Puts the archive `bytes` of `source` into the cache and returns its path.
+pub fn store(source: &str, bytes: &[u8]) -> Result<PathBuf> {
+    let ext = extension(source).unwrap_or(".tar");
+    let dir = archives_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let actual = sha256_hex(bytes);
+    let path = dir.join(format!("{}{}", actual, ext));
+    let staging = dir.join(format!(".{}.{}", actual, std::process::id()));
+    fs::File::create(&staging)
+        .and_then(|mut file| file.write_all(bytes))
+        .with_context(|| format!("Failed to write {}", staging.display()))?;
+    fs::rename(&staging, &path)?;
+    Ok(path)
+}
+
+/// Path of the cached archive of `source` with hash `sha256`, downloading it if needed.
+pub fn cached(source: &str, sha256: &str) -> Result<PathBuf> {
+    fetch(source, Some(&sha256.to_lowercase())).map(|(path, _)| path)
+}
+
+/// Resolves `revision` of the archive at `source` to its SHA-256. Archives have
+/// no history: without a pinned hash the archive is downloaded and its hash
+/// becomes the pin, unless a vendor bundle already provided it.
+pub fn resolve(source: &str, revision: &Revision) -> Result<String> {
+    match revision {
+        Revision::Head => match vendor::vendored(source).and_then(|v| v.head) {
+            Some(sha) => Ok(sha),
+            None => fetch(source, None).map(|(_, sha)| sha),
+        },
+        Revision::Sha256(sha) => fetch(source, Some(&sha.to_lowercase())).map(|(_, sha)| sha),
+        other => anyhow::bail!("'{}' is an archive and has no {}. Pin it with a sha256 instead.", source, other),
+    }
+}
+
+/// Whether the archive with hash `sha256` is already unpacked in the cache.
+pub fn is_unpacked(sha256: &str) -> bool {
+    archives_dir().is_ok_and(|dir| dir.join(sha256).is_dir())
+}
+
+/// Returns the unpacked contents of the archive at `source`, verifying that it
+/// hashes to `sha256`. A single top-level directory, as most tarballs have, is
+/// treated as the root.
+pub fn unpack(source: &str, sha256: &str) -> Result<PathBuf> {
+    anyhow::ensure!(is_sha256(sha256), "'{}' is not a SHA-256 hash", sha256);
+    let sha256 = sha256.to_lowercase();
+    let (archive, _) = fetch(source, Some(&sha256))?;
+    let dir = archives_dir()?.join(&sha256);
+    if !dir.exists() {
+        let staging = archives_dir()?.join(format!(".{}.{}.d", sha256, std::process::id()));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+        let mut command = if archive.extension().is_some_and(|ext| ext == "zip") {
+            let mut command = Command::new("unzip");
+            command.arg("-q").arg(&archive).arg("-d").arg(&staging);
+            command
+        } else {
+            let mut command = Command::new("tar");
+            command.arg("-xf").arg(&archive).arg("-C").arg(&staging);
+            command
+        };
+        let output = command.output()
+            .context("Failed to unpack archive. Ensure tar and unzip are installed and accessible from the command line.")?;
+        if !output.status.success() {
+            let _ = fs::remove_dir_all(&staging);
+            anyhow::bail!("Failed to unpack '{}': {}", source, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        // Another vpm process may have finished unpacking first; either copy is fine.
+        if fs::rename(&staging, &dir).is_err() {
+            fs::remove_dir_all(&staging)?;
+        }
+    }
+    Ok(root(&dir))
+}
+
+fn root(dir: &Path) -> PathBuf {
+    let entries: Vec<PathBuf> = fs::read_dir(dir).into_iter().flatten()
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .collect();
+    match entries.as_slice() {
+        [only] if only.is_dir() => only.clone(),
+        _ => dir.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_archives_by_extension() {
+        assert_eq!(extension("https://vendor.example.com/ip/fifo-1.2.tar.gz"), Some(".tar.gz"));
+        assert_eq!(extension("./drops/FIFO.TGZ"), Some(".tgz"));
+        assert_eq!(extension("https://example.com/ip.zip?token=abc#top"), Some(".zip"));
+        assert_eq!(extension("ip.tar.xz"), Some(".tar.xz"));
+        assert_eq!(extension("https://github.com/ZipCPU/zipcpu"), None);
+        assert_eq!(extension("rtl/tar.v"), None);
+        assert!(is_archive("ip-1.0.tar"));
+        assert!(!is_archive("https://example.com/ip.tar.gz.sig"));
+    }
+
+    #[test]
+    fn hashes_contents_as_hex() {
+        let hash = sha256_hex(b"abc");
+        assert_eq!(hash, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert!(is_sha256(&hash));
+        assert!(is_sha256(&hash.to_uppercase()));
+        assert!(!is_sha256(&hash[1..]));
+        assert!(!is_sha256(&"g".repeat(64)));
+    }
+}
@@ -0,0 +1,322 @@
+use std::cell::OnceCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::archive;
+use crate::git::{self, Revision};
+use crate::index::ModuleIndex;
+
+/// Per-user cache of upstream repositories, laid out as
+///
+/// ```text
+/// <data dir>/cache/git/db/<name>-<hash>/               bare mirror of one URL
+/// <data dir>/cache/git/checkouts/<name>-<hash>/<sha>/  working tree of one commit
+/// <data dir>/cache/archives/<sha256>/                  unpacked archive
+/// ```
+///
+/// Mirrors are fetched incrementally and never deleted by includes; checkouts
+/// are immutable once created, so every command reading the same commit shares them.
+pub fn cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("VPM_CACHE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    ProjectDirs::from("com", "instachip", "vpm-pro")
+        .map(|dirs| dirs.data_dir().join("cache"))
+        .ok_or_else(|| anyhow::anyhow!("Failed to determine the vpm cache directory. Set VPM_CACHE_DIR to choose one."))
+}
+
+fn db_dir() -> Result<PathBuf> {
+    Ok(cache_dir()?.join("git").join("db"))
+}
+
+fn checkouts_dir() -> Result<PathBuf> {
+    Ok(cache_dir()?.join("git").join("checkouts"))
+}
+
+/// Directory name for `url`: readable repository name plus a hash of the full
+/// URL, so repositories that share a name never collide.
+pub fn repo_key(url: &str) -> String {
+    let url = git::canonical(url);
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+    let name: String = url.rsplit('/').next().unwrap_or(url).chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let digest = Sha256::digest(url.as_bytes());
+    let hash: String = digest.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}", name, hash)
+}
+
+/// Where the bare mirror of `url` lives, whether or not it exists yet.
+pub fn mirror_path(url: &str) -> Result<PathBuf> {
+    Ok(db_dir()?.join(repo_key(url)))
+}
+
+/// Returns the bare mirror of `url`, creating it (without fetching) if needed.
+/// The canonical upstream URL is kept in the mirror's `vpm.url` config, since
+/// `remote.origin.url` holds where it is fetched from after mirror rules.
+pub fn mirror(url: &str) -> Result<PathBuf> {
+    let path = mirror_path(url)?;
+    if path.exists() {
+        return Ok(path);
+    }
+    fs::create_dir_all(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let init = git::run_in(&path, &["init", "--bare", "--quiet"])
+        .and_then(|_| git::run_in(&path, &["remote", "add", "origin", &git::remote(url)]))
+        .and_then(|_| git::run_in(&path, &["config", "vpm.url", &git::canonical(url)]))
+        .and_then(|_| git::run_in(&path, &["config", "remote.origin.fetch", "+refs/heads/*:refs/heads/*"]))
+        .and_then(|_| git::run_in(&path, &["config", "--add", "remote.origin.fetch", "+refs/tags/*:refs/tags/*"]));
+    if let Err(e) = init {
+        let _ = fs::remove_dir_all(&path);
+        return Err(e.context(format!("Failed to create cache mirror for '{}'", url)));
+    }
+    Ok(path)
+}
+
+fn resolve_local(mirror: &Path, commit: &str) -> Option<String> {
+    git::run_in(mirror, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", commit)]).ok()
+}
+
+/// Whether `commit` of `url` can be checked out without touching the network.
+pub fn has_commit(url: &str, commit: &str) -> bool {
+    if git::is_plain_directory(url) {
+        return true;
+    }
+    if archive::is_archive(url) {
+        return archive::is_unpacked(commit);
+    }
+    let Ok(path) = mirror_path(url) else {
+        return false;
+    };
+    path.exists() && resolve_local(&path, commit).is_some()
+}
+
+/// Makes sure the mirror of `url` contains `commit` and returns its full SHA.
+///
+/// Commits already in the mirror are never fetched again. Full hashes are
+/// fetched directly; abbreviated ones require fetching every branch and tag
+/// first. Fetched commits are kept alive by a `refs/vpm/<sha>` ref so that
+/// commits which are not on any branch survive a `git gc`.
+pub fn fetch_commit(url: &str, commit: &str) -> Result<String> {
+    let path = mirror(url)?;
+    let sha = match resolve_local(&path, commit) {
+        Some(sha) => sha,
+        None => {
+            // Mirror rules may have changed since the mirror was created.
+            git::run_in(&path, &["remote", "set-url", "origin", &git::remote(url)])?;
+            git::run_in(&path, &["config", "vpm.url", &git::canonical(url)])?;
+            let direct = git::is_full_commit_hash(commit)
+                && git::run_remote(&path, url, &["fetch", "--quiet", "origin", commit]).is_ok();
+            if !direct {
+                git::run_remote(&path, url, &["fetch", "--quiet", "--prune", "origin"])
+                    .with_context(|| format!("Failed to fetch repository from URL: '{}'", url))?;
+            }
+            resolve_local(&path, commit)
+                .ok_or_else(|| anyhow::anyhow!("Commit '{}' does not exist in '{}'", commit, url))?
+        }
+    };
+    git::run_in(&path, &["update-ref", &format!("refs/vpm/{}", sha), &sha])?;
+    Ok(sha)
+}
+
+/// One upstream commit checked out in the cache. A command opens it once and
+/// hands it to everything that reads from that commit, so a recursive include
+/// or update never fetches or checks out the same repository twice.
+#[derive(Debug, Clone)]
+pub struct Checkout {
+    pub url: String,
+    /// Full SHA of the checked out commit.
+    pub commit: String,
+    pub root: PathBuf,
+    index: OnceCell<ModuleIndex>,
+}
+
+impl Checkout {
+    /// The module index of this checkout, built on first use.
+    pub fn index(&self) -> &ModuleIndex {
+        self.index.get_or_init(|| ModuleIndex::build(&self.root))
+    }
+
+    /// What to record in vpm.toml for a module included from this checkout
+    /// at `revision`.
+    pub fn pinned(&self, revision: &Revision) -> Revision {
+        if self.commit.is_empty() {
+            // Plain directories have no commit to pin.
+            Revision::Head
+        } else if archive::is_archive(&self.url) {
+            Revision::Sha256(self.commit.clone())
+        } else {
+            revision.pinned(&self.commit)
+        }
+    }
+}
+
+/// Returns a working tree of `url` at `commit`, fetching and checking it out on first use.
+///
+/// Local directories that are not git repositories are used in place; for
+/// archives, `commit` is the archive's SHA-256.
+pub fn checkout(url: &str, commit: &str) -> Result<Checkout> {
+    if git::is_plain_directory(url) {
+        let root = Path::new(url).canonicalize()?;
+        return Ok(Checkout { url: url.to_string(), commit: String::new(), root, index: OnceCell::new() });
+    }
+    if archive::is_archive(url) {
+        let root = archive::unpack(url, commit)?;
+        return Ok(Checkout { url: url.to_string(), commit: commit.to_lowercase(), root, index: OnceCell::new() });
+    }
+    let sha = fetch_commit(url, commit)?;
+    let parent = checkouts_dir()?.join(repo_key(url));
+    let path = parent.join(&sha);
+    if !path.exists() {
+        fs::create_dir_all(&parent)?;
+        let staging = parent.join(format!(".{}.{}", sha, std::process::id()));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        let mirror = mirror(url)?;
+        let result = git::run_in(&parent, &["clone", "--quiet", "--shared", "--no-checkout", &mirror.to_string_lossy(), &staging.to_string_lossy()])
+            .and_then(|_| git::run_in(&staging, &["checkout", "--quiet", "--detach", &sha]))
+            .and_then(|_| git::head_commit(&staging));
+        match result {
+            Ok(head) if head == sha => {}
+            Ok(head) => {
+                let _ = fs::remove_dir_all(&staging);
+                anyhow::bail!("Requested commit '{}' of '{}' but the working tree is at '{}'", sha, url, head);
+            }
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(e.context(format!("Failed to check out '{}' at '{}'", url, sha)));
+            }
+        }
+        // Another vpm process may have finished the same checkout first; either copy is fine.
+        if fs::rename(&staging, &path).is_err() {
+            fs::remove_dir_all(&staging)?;
+        }
+    }
+    touch(&path);
+    Ok(Checkout { url: url.to_string(), commit: sha, root: path, index: OnceCell::new() })
+}
+
+fn touch(checkout: &Path) {
+    let _ = fs::write(checkout.join(".git").join("vpm-last-used"), "");
+}
+
+fn last_used(checkout: &Path) -> SystemTime {
+    fs::metadata(checkout.join(".git").join("vpm-last-used"))
+        .or_else(|_| fs::metadata(checkout))
+        .and_then(|m| m.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path).into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn subdirs(path: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(path).into_iter().flatten()
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{:.1} GiB", b as f64 / (1u64 << 30) as f64),
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+/// Prints every cached repository with its size and checked out commits.
+pub fn list() -> Result<()> {
+    let db = db_dir()?;
+    let mirrors = subdirs(&db);
+    let archives = subdirs(&cache_dir()?.join("archives"));
+    if mirrors.is_empty() && archives.is_empty() {
+        println!("The cache at {} is empty.", cache_dir()?.display());
+        return Ok(());
+    }
+    let mut total = 0;
+    for mirror in mirrors {
+        let key = mirror.file_name().unwrap_or_default().to_string_lossy().to_string();
+        // Mirrors created before `vpm.url` was recorded only know where they are fetched from.
+        let url = git::run_in(&mirror, &["config", "--get", "vpm.url"])
+            .or_else(|_| git::run_in(&mirror, &["config", "--get", "remote.origin.url"]))
+            .unwrap_or_else(|_| key.clone());
+        let checkouts = subdirs(&checkouts_dir()?.join(&key));
+        let size = dir_size(&mirror) + checkouts.iter().map(|c| dir_size(c)).sum::<u64>();
+        total += size;
+        println!("{} ({})", url, format_size(size));
+        for checkout in checkouts {
+            println!("    {}", checkout.file_name().unwrap_or_default().to_string_lossy());
+        }
+    }
+    for archive in archives {
+        let size = dir_size(&archive);
+        total += size;
+        println!("archive sha256:{} ({})", archive.file_name().unwrap_or_default().to_string_lossy(), format_size(size));
+    }
+    println!("Total: {} in {}", format_size(total), cache_dir()?.display());
+    Ok(())
+}
+
+/// Deletes checkouts not used for `max_age`, drops the refs that kept their
+/// commits alive, and compacts the mirrors.
+pub fn gc(max_age: Duration) -> Result<()> {
+    let now = SystemTime::now();
+    let mut freed = 0;
+    for repo in subdirs(&checkouts_dir()?) {
+        let mirror = db_dir()?.join(repo.file_name().unwrap_or_default());
+        for checkout in subdirs(&repo) {
+            let age = now.duration_since(last_used(&checkout)).unwrap_or_default();
+            if age > max_age {
+                freed += dir_size(&checkout);
+                fs::remove_dir_all(&checkout)
+                    .with_context(|| format!("Failed to remove {}", checkout.display()))?;
+                let sha = checkout.file_name().unwrap_or_default().to_string_lossy();
+                if mirror.exists() && git::is_full_commit_hash(&sha) {
+                    git::run_in(&mirror, &["update-ref", "-d", &format!("refs/vpm/{}", sha)])
+                        .with_context(|| format!("Failed to drop refs/vpm/{} from {}", sha, mirror.display()))?;
+                }
+            }
+        }
+        if subdirs(&repo).is_empty() {
+            let _ = fs::remove_dir(&repo);
+        }
+    }
+    for mirror in subdirs(&db_dir()?) {
+        let before = dir_size(&mirror);
+        git::run_in(&mirror, &["gc", "--quiet", "--auto"])
+            .with_context(|| format!("Failed to compact {}", mirror.display()))?;
+        freed += before.saturating_sub(dir_size(&mirror));
+    }
+    println!("Freed {}", format_size(freed));
+    Ok(())
+}
+
+/// Removes the entire cache.
+pub fn clean() -> Result<()> {
+    let dir = cache_dir()?;
+    if dir.exists() {
+        let size = dir_size(&dir);
+        fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+        println!("Removed {} ({})", dir.display(), format_size(size));
+    } else {
+        println!("The cache at {} is empty.", dir.display());
+    }
+    Ok(())
+}
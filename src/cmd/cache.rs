@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::cache;
+use crate::cmd::{Cache, CacheAction, Execute};
+
+impl Execute for Cache {
+    async fn execute(&self) -> Result<()> {
+        match &self.action {
+            CacheAction::Ls => cache::list(),
+            CacheAction::Gc { max_age } => {
+                let seconds = max_age.checked_mul(24 * 60 * 60)
+                    .with_context(|| format!("--max-age {} is too large", max_age))?;
+                cache::gc(Duration::from_secs(seconds))
+            }
+            CacheAction::Clean => cache::clean(),
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Debug, Parser)]
-#[clap(
+#[command(
     about = "VPM - Verilog Package Manager",
     author,
     version,
@@ -9,16 +9,61 @@ use clap::Parser;
     disable_help_subcommand = true,
     after_help = "Run 'vpm <COMMAND> --help' for more information on a specific command."
 )]
+pub struct Cli {
+    #[arg(long, global = true, help = "Never access the network: dependencies are served from the repository cache, and commands that need the network fail right away. Documentation is generated with a local model. Also enabled by setting VPM_OFFLINE=1. The license must have been checked online within the last 7 days.")]
+    pub offline: bool,
+    #[arg(long, global = true, value_name = "PATH", help = "Path to the vpm.toml of the project. By default vpm uses the closest vpm.toml in the current directory or its parents, and resolves vpm.lock and vpm_modules/ from that directory.")]
+    pub manifest_path: Option<String>,
+    #[arg(short = 'C', global = true, value_name = "DIR", help = "Run as if vpm was started in DIR.")]
+    pub directory: Option<String>,
+    #[command(subcommand)]
+    pub command: Cmd,
+}
+
+#[derive(Debug, Subcommand)]
 pub enum Cmd {
     #[command(
-        about = "vpm include <MODULE_URL> [--repo] [--riscv] [--commit <HASH>] // Add a module or repository to your project",
-        long_about = "Include a module with one command. VPM's internal parser will identify and configure any subdependencies."
+        about = "vpm init [--name <NAME>] [--version <VERSION>] [--author <AUTHOR>]... [--description <TEXT>] [--license <LICENSE>] [--yes] [--scan] // Create a vpm.toml in the current directory",
+        long_about = "Create a vpm.toml for a new project in the current directory. Values not given as flags are asked for interactively, or take their defaults with --yes or when input is not a terminal. Every other command works on an existing vpm.toml. With --scan, the existing Verilog and SystemVerilog sources are recorded in vpm.lock, and their top modules and unresolved instantiations are listed.",
+        disable_version_flag = true
+    )]
+    Init(Init),
+
+    #[command(
+        about = "vpm include <SOURCE> [--path <PATH> | --select <GLOB>... | --module <NAME>... | --all] [--repo] [--riscv] [--commit <HASH> | --tag <TAG> | --branch <BRANCH> | --version <RANGE> | --sha256 <HASH>] // Add a module or repository to your project",
+        long_about = "Include a module with one command. VPM's internal parser will identify and configure any subdependencies.",
+        disable_version_flag = true
     )]
     Include(Include),
 
+    #[command(
+        about = "vpm show <SOURCE> <MODULE> [--json] // Inspect a module of a repository without including it",
+        long_about = "Show the ports, parameters and submodule tree of a module, with the license and latest commit of its repository. The repository is read through the repository cache; nothing is written to vpm_modules/, vpm.toml or vpm.lock."
+    )]
+    Show(Show),
+
+    #[command(
+        about = "vpm fetch [--locked] [--frozen] // Rebuild vpm_modules/ from vpm.toml and vpm.lock",
+        long_about = "Restore every file recorded in vpm.lock at its locked commit and verify its checksum. Use this after cloning a project to get the exact dependencies your teammates built with."
+    )]
+    Fetch(Fetch),
+
+    #[command(
+        about = "vpm cache <ls|gc|clean> // Inspect or prune the repository cache",
+        long_about = "Manage the per-user cache of upstream repositories. vpm keeps a mirror of every repository it includes from, so repeated includes, updates and fetches only download what changed."
+    )]
+    Cache(Cache),
+
+    #[command(
+        about = "vpm vendor <export|import> // Move resolved dependencies to a machine without network access",
+        long_about = "Pack every upstream revision recorded in vpm.lock into a single archive, and import such an archive into the repository cache of an offline machine. Imported sources are then resolved by vpm include, update and fetch without network access."
+    )]
+    Vendor(Vendor),
+
     #[command(
         about = "vpm update <MODULE_PATH> [--commit <HASH>] // Update a module to its latest version",
-        long_about = "Update a specific module to its latest version. This command checks for updates to the specified module and applies them if available."
+        long_about = "Update a specific module to its latest version. This command checks for updates to the specified module and applies them if available.",
+        disable_version_flag = true
     )]
     Update(Update),
 
@@ -103,27 +148,122 @@ pub enum Cmd {
 #[derive(Debug, Parser)]
 pub struct Upgrade {}
 
+#[derive(Debug, Parser)]
+pub struct Init {
+    #[arg(long, help = "Name of the package. Defaults to the name of the directory.")]
+    pub name: Option<String>,
+    #[arg(long, help = "Version of the package (default 0.1.0).")]
+    pub version: Option<String>,
+    #[arg(long = "author", value_name = "AUTHOR", help = "Author of the package, e.g. 'Jane Doe <jane@example.com>'. Repeat for several authors.")]
+    pub authors: Vec<String>,
+    #[arg(long, help = "One-line description of the package.")]
+    pub description: Option<String>,
+    #[arg(long, help = "License of the package, as an SPDX expression.")]
+    pub license: Option<String>,
+    #[arg(long, short, help = "Do not prompt; use the default for every value not given as a flag.")]
+    pub yes: bool,
+    #[arg(long, help = "Parse the .v and .sv files below the current directory, record them in vpm.lock, and list the top modules and the instantiated modules that no file defines.")]
+    pub scan: bool,
+}
+
 #[derive(Debug, Parser)]
 pub struct Include {
-    #[arg(long, short, help = "If this flag is set, the URL will be treated as a full repository. If not set, the URL will be treated as a single module.")]
+    #[arg(long, short, help = "Choose the modules to include from the whole repository. With this flag, 'AUTHOR_NAME/REPO_NAME' is accepted as a shorthand for a GitHub repository.")]
     pub repo: bool,
-    #[arg(help = "GitHub URL of the module to include. This should point to a single .v or .sv file in GitHub. If --repo is set, <URL> should not be a full repository URL, but rather 'AUTHOR_NAME/REPO_NAME'")]
+    #[arg(help = "Source to include from: any git URL (https, ssh, file://) or a local directory. A link to a .v or .sv file on GitHub or GitLab ('<REPO>/blob/<BRANCH>/<PATH>') includes that single module.")]
     pub url: String,
+    #[arg(long, help = "Path of the module inside the source, e.g. 'rtl/core/pfcache.v'. If not set, and <URL> does not link to a file, you choose the modules to include from the repository: with --select, --module or --all, or else in a picker when input is a terminal.")]
+    pub path: Option<String>,
+    #[arg(long, value_name = "GLOB", conflicts_with_all = ["path", "all"], help = "Include the .v and .sv files matching GLOB instead of choosing them in the picker, e.g. 'rtl/**/*.sv'. Patterns without '/' match file names. Can be repeated.")]
+    pub select: Vec<String>,
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["path", "all"], help = "Include the file declaring module NAME instead of choosing it in the picker. Can be repeated.")]
+    pub module: Vec<String>,
+    #[arg(long, conflicts_with = "path", help = "Include every .v and .sv file of the repository instead of choosing them in the picker.")]
+    pub all: bool,
     #[arg(long, help = "Include RISC-V specific modules. Use this flag when including modules designed specifically for RISC-V architectures.")]
     pub riscv: bool,
-    #[arg(long, help = "Commit hash of the module to include. This should be a valid commit hash from the module's repository.")]
+    #[arg(long, alias = "rev", conflicts_with_all = ["tag", "branch", "version", "sha256"], help = "Commit hash of the module to include. This should be a valid commit hash from the module's repository.")]
     pub commit: Option<String>,
-    #[arg(long, help = "Create documentation for the module.")]
+    #[arg(long, conflicts_with_all = ["branch", "version", "sha256"], help = "Tag of the module's repository to include, e.g. 'v1.2.0'.")]
+    pub tag: Option<String>,
+    #[arg(long, conflicts_with_all = ["version", "sha256"], help = "Branch of the module's repository to include. The branch is resolved to its current commit, which is recorded in vpm.lock.")]
+    pub branch: Option<String>,
+    #[arg(long, conflicts_with = "sha256", help = "Semver range matched against the repository's tags, e.g. '^1.2'. The newest matching tag is included.")]
+    pub version: Option<String>,
+    #[arg(long, help = "Expected SHA-256 of an archive source (.tar.gz, .tgz, .zip, ...). If not set, the hash of the downloaded archive is recorded in vpm.toml.")]
+    pub sha256: Option<String>,
+    #[arg(long, value_name = "PREFIX", num_args = 0..=1, require_equals = true, default_missing_value = "", help = "Prefix the names of the included modules, in their declarations and instantiations, to keep them apart from modules of the same name in other dependencies, e.g. 'fifo' becomes 'zipcpu_fifo'. The files and their folder get the prefix too. PREFIX defaults to the repository name. The renaming is recorded in vpm.lock.")]
+    pub namespace: Option<String>,
+    #[arg(long, help = "Create documentation for the module. With --local-model or --offline, it is generated with a local model.")]
     pub with_docs: bool,
-    #[arg(long, help = "Generates documentation in offline mode for code security. This flag is ignored if --with_docs is not set.")]
-    pub offline: bool,
+    #[arg(long, help = "Generates documentation with a local model for code security. This flag is ignored if --with-docs is not set.")]
+    pub local_model: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Show {
+    #[arg(help = "Source of the module: any git URL (https, ssh, file://), an archive or a local directory.")]
+    pub source: String,
+    #[arg(help = "Name of the module, or path of its file inside the source, e.g. 'rtl/core/pfcache.v'.")]
+    pub module: String,
+    #[arg(long, help = "Print the details as JSON.")]
+    pub json: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Fetch {
+    #[arg(long, help = "Fail if vpm.toml and vpm.lock disagree, or if vpm.lock would have to change.")]
+    pub locked: bool,
+    #[arg(long, help = "Same as --locked, and additionally forbid network access. Missing files are restored from the repository cache; fails if a file is missing and its commit is not cached.")]
+    pub frozen: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Cache {
+    #[command(subcommand)]
+    pub action: CacheAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheAction {
+    #[command(about = "List cached repositories, their size and checked out commits")]
+    Ls,
+    #[command(about = "Remove checkouts that have not been used recently and compact the mirrors")]
+    Gc {
+        #[arg(long, default_value_t = 30, help = "Remove checkouts not used in this many days.")]
+        max_age: u64,
+    },
+    #[command(about = "Remove the entire cache")]
+    Clean,
+}
+
+#[derive(Debug, Parser)]
+pub struct Vendor {
+    #[command(subcommand)]
+    pub action: VendorAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VendorAction {
+    #[command(about = "Pack every repository revision recorded in vpm.lock into one archive")]
+    Export {
+        #[arg(long, short, default_value = "vpm-vendor.tar.gz", help = "Path of the archive to write.")]
+        output: String,
+    },
+    #[command(about = "Import an archive written by `vpm vendor export` into the repository cache")]
+    Import {
+        #[arg(required_unless_present = "remove", conflicts_with = "remove", help = "Path of the archive to import.")]
+        bundle: Option<String>,
+        #[arg(long, help = "Forget every imported source, so that it is fetched from upstream again.")]
+        remove: bool,
+    },
 }
 
 #[derive(Debug, Parser)]
 pub struct Update {
     #[arg(help = "Full module path of the module to update. This should be the complete path to the module file within your project structure.")]
     pub module_path: String,
-    #[arg(long, help = "Update to the given commit hash. If not set, the latest commit hash will be used.")]
+    #[arg(long, help = "Update to the given commit hash, or to the newest tag matching a semver range such as '^1.2'. If not set, the latest commit hash will be used.")]
     pub version: Option<String>,
 }
 
@@ -137,10 +277,12 @@ pub struct Remove {
 pub struct Docs {
     #[arg(help = "Path of the module to generate documentation for. This should be the path to the module file within your project structure, starting with 'vpm_modules/'.")]
     pub module_path: String,
-    #[arg(long, help = "If this flag is set, the module path will be treated as a link to a .v or .sv file in a GitHub repository. If not set, the path will be treated as a local file path.")]
+    #[arg(long, help = "If this flag is set, the module path will be treated as a source (git URL or local directory) or a link to a .v or .sv file on GitHub or GitLab. If not set, the path will be treated as a local file path.")]
     pub from_repo: bool,
-    #[arg(long, help = "Generate documentation in offline mode for code security.")]
-    pub offline: bool,
+    #[arg(long, help = "Path of the module inside the source given with --from-repo.")]
+    pub path: Option<String>,
+    #[arg(long, help = "Generate documentation with a local model for code security. Always the case with --offline.")]
+    pub local_model: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -206,6 +348,22 @@ pub struct Run {
 pub struct Config {
     #[arg(long, help = "Enable or disable anonymous usage data collection. Set to false to opt-out of data collection.")]
     pub analytics: Option<bool>,
+    #[arg(long, value_name = "HOST=TOKEN", help = "Access token for private repositories on HOST, e.g. 'github.com=ghp_...'. Leave TOKEN empty to remove it.")]
+    pub token: Option<String>,
+    #[arg(long, value_name = "HOST=PATH", help = "SSH private key for repositories on HOST, e.g. 'git.example.com=~/.ssh/id_ed25519'. Leave PATH empty to remove it.")]
+    pub ssh_key: Option<String>,
+    #[arg(long, value_name = "URL", help = "Proxy for all HTTP requests and git fetches, e.g. 'http://proxy.corp:3128'. Pass an empty URL to remove it.")]
+    pub proxy: Option<String>,
+    #[arg(long, value_name = "PATH", help = "PEM file with root certificates to trust in addition to the system ones, e.g. for a TLS-intercepting proxy. Pass an empty PATH to remove it.")]
+    pub ca_bundle: Option<String>,
+    #[arg(long, value_name = "SECS", help = "Seconds to wait for a connection before giving up (default 10).")]
+    pub connect_timeout: Option<u64>,
+    #[arg(long, value_name = "SECS", help = "Seconds to wait for a response before giving up (default 60).")]
+    pub timeout: Option<u64>,
+    #[arg(long, help = "How often to retry requests that failed to connect, timed out or hit a server error (default 3).")]
+    pub retries: Option<u32>,
+    #[arg(long, num_args = 2, value_names = ["FROM", "TO"], help = "Fetch every URL starting with FROM from TO instead, e.g. --mirror https://github.com/ https://git.corp/mirror/github/. Pass an empty TO to remove the rule.")]
+    pub mirror: Option<Vec<String>>,
 }
 
 #[derive(Debug, Parser)]
@@ -1,5 +1,6 @@
 use crate::cmd::{Execute, Config};
-use crate::config_man::set_analytics;
+use crate::config_man::{set_analytics, set_host_auth, set_http_option, set_mirror};
+use toml_edit::Value;
 use anyhow::{Context, Result};
 
 impl Execute for Config {
@@ -9,6 +10,44 @@ impl Execute for Config {
             set_analytics(analytics).context("Failed to set analytics")?;
             println!("Analytics set to: {}", analytics);
         }
+        if let Some(token) = &self.token {
+            let (host, token) = token.split_once('=').context("Pass the token as HOST=TOKEN, e.g. 'github.com=ghp_...'")?;
+            set_host_auth(host, "token", token).context("Failed to set token")?;
+            if token.is_empty() {
+                println!("Token for {} removed", host);
+            } else {
+                println!("Token for {} saved", host);
+            }
+        }
+        if let Some(ssh_key) = &self.ssh_key {
+            let (host, path) = ssh_key.split_once('=').context("Pass the key as HOST=PATH, e.g. 'github.com=~/.ssh/id_ed25519'")?;
+            set_host_auth(host, "ssh_key", path).context("Failed to set SSH key")?;
+            if path.is_empty() {
+                println!("SSH key for {} removed", host);
+            } else {
+                println!("SSH key for {} set to: {}", host, path);
+            }
+        }
+        for (key, value) in [("proxy", &self.proxy), ("ca_bundle", &self.ca_bundle)] {
+            if let Some(value) = value {
+                set_http_option(key, (!value.is_empty()).then(|| Value::from(value.as_str()))).context("Failed to set HTTP option")?;
+                println!("{} set to: {}", key, if value.is_empty() { "(none)" } else { value });
+            }
+        }
+        for (key, value) in [("connect_timeout", self.connect_timeout), ("timeout", self.timeout), ("retries", self.retries.map(u64::from))] {
+            if let Some(value) = value {
+                set_http_option(key, Some(Value::from(value as i64))).context("Failed to set HTTP option")?;
+                println!("{} set to: {}", key, value);
+            }
+        }
+        if let Some([from, to]) = self.mirror.as_deref() {
+            set_mirror(from, to).context("Failed to set mirror")?;
+            if to.is_empty() {
+                println!("Mirror for {} removed", from);
+            } else {
+                println!("Fetching {} from {}", from, to);
+            }
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
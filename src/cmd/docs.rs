@@ -5,22 +5,26 @@ use std::path::PathBuf;
 use std::fs;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::process::{Command, Stdio};
-use parsv;
 // use std::path::Path;
 
 use crate::cmd::{Execute, Docs};
+use crate::cmd::include::{include_repo_from_url, parse_source};
+use crate::git::{resolve_revision, Revision};
+use crate::network;
 
 impl Execute for Docs {
     async fn execute(&self) -> Result<()> {
         if self.from_repo {
-            let content = fetch_module_content(&self.module_path).await
+            let (source, module_path) = parse_source(&self.module_path, self.path.as_deref(), false);
+            let module_path = module_path.ok_or_else(|| anyhow!("Pass the path of the module inside '{}' with --path", source))?;
+            let content = fetch_module_content(&source, &module_path)
                 .context("Failed to fetch module content. Please check your internet connection and ensure the provided URL is correct.")?;
-            let file_name = self.module_path.split('/').last().unwrap_or(&self.module_path);
+            let file_name = module_path.split('/').next_back().unwrap_or(&module_path);
             let folder_name = file_name.split('.').next().unwrap_or(file_name);
             let destination = PathBuf::from("./vpm_modules").join(folder_name);
             fs::create_dir_all(&destination)
                 .context("Failed to create destination directory. Please check if you have write permissions in the current directory.")?;
-            if self.offline {
+            if self.local_model || network::is_offline() {
                 generate_docs_offline(&self.module_path, &content, Some(destination.join(format!("{}_README.md", folder_name)))).await
                     .context("Failed to generate documentation offline. Please check the module content and try again.")?;
             } else {
@@ -35,7 +39,7 @@ impl Execute for Docs {
                     .with_context(|| format!("Failed to read module file: {}. Please ensure you have read permissions for this file.", full_module_path.display()))?;
                 // println!("Generating documentation for local module '{}'", self.module_path);
                 let readme_path = full_module_path.with_file_name(format!("{}_README.md", full_module_path.file_stem().unwrap().to_str().unwrap()));
-                if self.offline {
+                if self.local_model || network::is_offline() {
                     generate_docs_offline(&self.module_path, &content, Some(readme_path)).await
                         .context("Failed to generate documentation offline for the local module. Please check the module content and try again.")?;
                 } else {
@@ -50,25 +54,13 @@ impl Execute for Docs {
     }
 }
 
-async fn fetch_module_content(url: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-
-    // Extract the raw content URL
-    let raw_url = url.replace("github.com", "raw.githubusercontent.com")
-                     .replace("/blob/", "/");
-
-    println!("Fetching content from URL: {}", raw_url);
-
-    // Fetch the content
-    let response = client.get(&raw_url).send().await.context("Failed to fetch module content. Please check your internet connection and ensure the provided URL is correct.")?;
-
-    if !response.status().is_success() {
-        return Err(anyhow::anyhow!("Failed to fetch module content: HTTP {}", response.status()));
-    }
-
-    let content = response.text().await.context("Failed to fetch module content. Please check your internet connection and ensure the provided URL is correct.")?;
-
-    Ok(content)
+/// Reads `module_path` of `source` at its current HEAD through the repository cache.
+fn fetch_module_content(source: &str, module_path: &str) -> Result<String> {
+    let commit = resolve_revision(source, &Revision::Head)?;
+    let repo = include_repo_from_url(source, &commit)?;
+    println!("Fetching content from: {}@{}:{}", source, repo.commit, module_path);
+    fs::read_to_string(repo.root.join(module_path))
+        .with_context(|| format!("'{}' does not exist in {}", module_path, source))
 }
 
 // fn format_text(text: &str) -> String {
@@ -85,16 +77,16 @@ pub async fn generate_docs(module_path: &str, content: &str, full_module_path: O
     pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}").unwrap());
     pb.set_message("Drawing pin diagram...");
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
-    parsv::generate_module_diagram(&content, &full_module_path_str).context("Failed to generate module diagram. Please try again, and if the issue persists, report it to the developers.")?;
+    parsv::generate_module_diagram(content, &full_module_path_str).context("Failed to generate module diagram. Please try again, and if the issue persists, report it to the developers.")?;
     pb.set_message("Understanding module...");
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
-    parsv::generate_documentation(&content, &full_module_path_str).await.context("Failed to generate documentation. Please try again, and if the issue persists, report it to the developers.")?;
+    parsv::generate_documentation(content, &full_module_path_str).await.context("Failed to generate documentation. Please try again, and if the issue persists, report it to the developers.")?;
     pb.set_message("Thinking of testcases...");
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
     parsv::add_testcases_to_documentation(&full_module_path_str, 1, 2, 2).await.context("Failed to add testcases to documentation. Please try again, and if the issue persists, report it to the developers.")?;
     pb.set_message("Drafting testbenches...");
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
-    parsv::generate_testbenches(&content, &full_module_path_str, 1, 2, 2).await.context("Failed to generate testbenches. Please try again, and if the issue persists, report it to the developers.")?;
+    parsv::generate_testbenches(content, &full_module_path_str, 1, 2, 2).await.context("Failed to generate testbenches. Please try again, and if the issue persists, report it to the developers.")?;
     pb.set_message("Running simulations...");
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
     parsv::run_testbenches(module_path, &(full_module_path_str.clone() + "/sim")).context("Failed to run testbenches. Please try again, and if the issue persists, report it to the developers.")?;
@@ -119,14 +111,14 @@ pub async fn generate_docs_offline(module_path: &str, content: &str, full_module
     pb.set_position(33);
 
     // Check if Ollama is installed
-    if !Command::new("ollama").arg("--version").output().is_ok() {
+    if Command::new("ollama").arg("--version").output().is_err() {
         pb.set_message("Ollama not found. Installing...");
         
         // Install Ollama
         let install_status = if cfg!(target_os = "macos") {
-            Command::new("brew").args(&["install", "ollama"]).status()
+            Command::new("brew").args(["install", "ollama"]).status()
         } else if cfg!(target_os = "linux") {
-            Command::new("curl").args(&["-fsSL", "https://ollama.ai/install.sh", "|", "sh"]).status()
+            Command::new("curl").args(["-fsSL", "https://ollama.ai/install.sh", "|", "sh"]).status()
         } else {
             return Err(anyhow::anyhow!("Unsupported operating system for Ollama installation"));
         };
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::cmd::{Execute, Fetch};
+use crate::cache;
+use crate::cmd::include::{include_repo_from_url, lock_path, write_header};
+use crate::git::{self, Revision};
+use crate::index::rename_modules;
+use crate::network;
+use crate::lockfile::{checksum, EntryKind, LockEntry, Lockfile};
+use crate::toml::VpmToml;
+use crate::cmd::wrapper;
+
+impl Execute for Fetch {
+    async fn execute(&self) -> Result<()> {
+        let locked = self.locked || self.frozen;
+        let vpm_toml = VpmToml::load("vpm.toml")?;
+        let mut lockfile = Lockfile::load("vpm.lock")?;
+
+        if locked {
+            let mismatches = compare_manifest_and_lockfile(&vpm_toml, &lockfile);
+            if !mismatches.is_empty() {
+                anyhow::bail!(
+                    "vpm.toml and vpm.lock disagree:\n  - {}\nRun `vpm fetch` without --locked, or re-include the dependencies, to update vpm.lock.",
+                    mismatches.join("\n  - ")
+                );
+            }
+        } else {
+            add_manifest_assets(&vpm_toml, &mut lockfile);
+        }
+
+        // Group the locked files by the upstream revision they come from so every
+        // revision is read at most once.
+        let mut revisions: BTreeMap<(String, String), Vec<usize>> = BTreeMap::new();
+        for (index, entry) in lockfile.packages.iter().enumerate().filter(|(_, e)| e.kind.is_copied()) {
+            revisions.entry((entry.source.clone(), entry.commit.clone())).or_default().push(index);
+        }
+
+        let mut restored = 0;
+        for ((source, commit), indices) in revisions {
+            let missing: Vec<usize> = indices.into_iter()
+                .filter(|&i| !is_up_to_date(&lockfile.packages[i]))
+                .collect();
+            if missing.is_empty() {
+                continue;
+            }
+            for &i in &missing {
+                let entry = &lockfile.packages[i];
+                if !entry.checksum.is_empty() && Path::new(&entry.full_path).exists() {
+                    anyhow::bail!(
+                        "'{}' does not match the checksum in vpm.lock. Remove it to restore the locked version, or run `vpm update` to pick up the change.",
+                        entry.full_path
+                    );
+                }
+            }
+            if network::is_offline() && !cache::has_commit(&source, &commit) {
+                anyhow::bail!(
+                    "{} file(s) from '{}' are missing, the repository cache does not contain commit '{}', and {} forbids network access: {}",
+                    missing.len(), source, commit, if self.frozen { "--frozen" } else { "offline mode" },
+                    missing.iter().map(|&i| lockfile.packages[i].full_path.as_str()).collect::<Vec<_>>().join(", ")
+                );
+            }
+            anyhow::ensure!(!commit.is_empty() || git::is_plain_directory(&source), "vpm.lock does not record a commit for '{}'. Re-include it to pin a revision.", source);
+
+            println!("Fetching {}@{}", source, commit);
+            let repo = include_repo_from_url(&source, &commit)
+                .with_context(|| format!("Failed to fetch '{}' at commit '{}'", source, commit))?;
+
+            for i in missing {
+                let entry = &mut lockfile.packages[i];
+                materialize(entry, &repo.root, locked)?;
+                restored += 1;
+            }
+        }
+
+        let generated = wrapper::sync(&vpm_toml, &mut lockfile, locked)?;
+
+        if !locked {
+            lockfile.save("vpm.lock")?;
+        }
+        if generated > 0 {
+            println!("{} wrapper(s) generated from the params in vpm.toml.", generated);
+        }
+        let locked_files = lockfile.packages.iter().filter(|e| e.kind.is_copied()).count();
+        println!("{} file(s) restored, {} locked file(s) in total.", restored, locked_files);
+        Ok(())
+    }
+}
+
+fn is_up_to_date(entry: &LockEntry) -> bool {
+    match fs::read(&entry.full_path) {
+        Ok(contents) => !entry.checksum.is_empty() && checksum(&contents) == entry.checksum,
+        Err(_) => false,
+    }
+}
+
+/// Copies the locked file out of the checked-out repository, renames its
+/// modules as recorded, and regenerates its header.
+fn materialize(entry: &mut LockEntry, repo_root: &Path, locked: bool) -> Result<()> {
+    anyhow::ensure!(!entry.path.is_empty(), "vpm.lock does not record the upstream path of '{}'. Re-include it to fix the lockfile.", entry.full_path);
+    let upstream = repo_root.join(&entry.path);
+    let mut contents = fs::read(&upstream)
+        .with_context(|| format!("'{}' does not exist in {}@{}", entry.path, entry.source, entry.commit))?;
+    if !entry.renames.is_empty() {
+        contents = rename_modules(&String::from_utf8_lossy(&contents), &entry.renames).into_bytes();
+    }
+
+    let actual = checksum(&contents);
+    if entry.checksum.is_empty() {
+        anyhow::ensure!(!locked, "vpm.lock has no checksum for '{}' and --locked forbids adding one", entry.full_path);
+        entry.checksum = actual;
+    } else if entry.checksum != actual {
+        anyhow::bail!(
+            "Checksum mismatch for '{}' from {}@{}: vpm.lock has {}, upstream has {}",
+            entry.full_path, entry.source, entry.commit, entry.checksum, actual
+        );
+    }
+
+    let full_path = PathBuf::from(&entry.full_path);
+    let target_path = full_path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(target_path)?;
+    fs::write(&full_path, &contents)?;
+    if entry.kind == EntryKind::Hdl {
+        let module_name = full_path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        write_header(target_path, module_name, &full_path, &String::from_utf8_lossy(&contents))?;
+    }
+    println!("Restored {}", entry.full_path);
+    Ok(())
+}
+
+fn file_name(path: &str) -> String {
+    path.rsplit(['/', '\\']).next().unwrap_or(path).to_string()
+}
+
+/// Where an asset listed in vpm.toml is copied: the folder of its top module.
+fn asset_path(top: &LockEntry, asset: &str) -> String {
+    let dir = Path::new(&top.full_path).parent().and_then(Path::parent).unwrap_or(Path::new("."));
+    lock_path(&dir.join(Path::new(asset).file_name().unwrap_or_default()))
+}
+
+/// Adds lockfile entries for the `assets` in vpm.toml that vpm.lock does not
+/// know yet; they are then restored like every other missing file.
+fn add_manifest_assets(vpm_toml: &VpmToml, lockfile: &mut Lockfile) {
+    for (source, modules) in vpm_toml.get_dependencies() {
+        for module in modules {
+            // Local top modules keep their assets next to them already.
+            let Some(top) = lockfile.top_entry(source, &module.top_module).filter(|e| e.kind.is_copied()).cloned() else {
+                continue;
+            };
+            for asset in &module.assets {
+                let full_path = asset_path(&top, asset);
+                if lockfile.get(&full_path).is_none() {
+                    lockfile.upsert(LockEntry {
+                        full_path,
+                        source: source.clone(),
+                        kind: EntryKind::Asset,
+                        commit: top.commit.clone(),
+                        path: asset.clone(),
+                        parents: vec![top.full_path.clone()],
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Lists every top module and asset that is in one file but not the other.
+fn compare_manifest_and_lockfile(vpm_toml: &VpmToml, lockfile: &Lockfile) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    let top_entries: Vec<&LockEntry> = lockfile.packages.iter().filter(|e| e.parents.is_empty() && e.kind.is_copied()).collect();
+
+    for (source, modules) in vpm_toml.get_dependencies() {
+        for module in modules {
+            let name = file_name(&module.top_module);
+            match lockfile.top_entry(source, &module.top_module) {
+                None => mismatches.push(format!("'{}' from {} is not in vpm.lock", name, source)),
+                // Restructured local files record no commit to compare.
+                Some(entry) if entry.kind == EntryKind::Local => {}
+                Some(entry) => {
+                    // Pinned commits are compared with the commit; tags, branches and ranges
+                    // with the revision they were resolved from, when vpm.lock records it.
+                    match module.revision() {
+                        Revision::Rev(rev) if !entry.commit.starts_with(&rev) => {
+                            mismatches.push(format!("'{}' from {} is at '{}' in vpm.toml but '{}' in vpm.lock", name, source, rev, entry.commit));
+                        }
+                        Revision::Sha256(sha256) if !entry.commit.eq_ignore_ascii_case(&sha256) => {
+                            mismatches.push(format!("'{}' from {} has sha256 '{}' in vpm.toml but '{}' in vpm.lock", name, source, sha256, entry.commit));
+                        }
+                        revision @ (Revision::Tag(_) | Revision::Branch(_) | Revision::Version(_))
+                            if !entry.revision.is_empty() && entry.revision != revision.to_string() =>
+                        {
+                            mismatches.push(format!("'{}' from {} asks for {} in vpm.toml but vpm.lock was resolved from {}", name, source, revision, entry.revision));
+                        }
+                        _ => {}
+                    }
+                    for asset in &module.assets {
+                        if lockfile.get(&asset_path(entry, asset)).is_none() {
+                            mismatches.push(format!("asset '{}' of '{}' is not in vpm.lock", asset, name));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for entry in top_entries {
+        let name = file_name(&entry.full_path);
+        let in_manifest = vpm_toml.get_dependencies().get(&entry.source)
+            .is_some_and(|modules| modules.iter().any(|m| file_name(&m.top_module) == name));
+        if !in_manifest {
+            mismatches.push(format!("'{}' from {} is in vpm.lock but not in vpm.toml", entry.full_path, entry.source));
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(tag: &str) -> VpmToml {
+        VpmToml::parse(&format!(
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[dependencies]\n\"https://github.com/a/b\" = [{{ top_module = \"fifo.v\", tag = \"{}\" }}]\n",
+            tag
+        )).unwrap()
+    }
+
+    #[test]
+    fn compares_the_requested_revision_of_top_modules() {
+        let lockfile = Lockfile {
+            packages: vec![LockEntry {
+                full_path: "vpm_modules/fifo/rtl/fifo.v".to_string(),
+                source: "https://github.com/a/b".to_string(),
+                commit: "0123456789abcdef0123456789abcdef01234567".to_string(),
+                revision: "tag v1".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(compare_manifest_and_lockfile(&manifest("v1"), &lockfile).is_empty());
+        assert_eq!(
+            compare_manifest_and_lockfile(&manifest("v2"), &lockfile),
+            ["'fifo.v' from https://github.com/a/b asks for tag v2 in vpm.toml but vpm.lock was resolved from tag v1"]
+        );
+    }
+}
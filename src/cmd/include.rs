@@ -1,6 +1,12 @@
 use crate::cmd::{Execute, Include};
 use crate::cmd::docs::{generate_docs, generate_docs_offline};
-use crate::toml::{add_dependency, add_top_module};
+use crate::toml::{add_dependency, add_top_module, get_assets, VpmToml};
+use crate::lockfile::{checksum, with_lockfile, EntryKind, LockEntry, Lockfile};
+use crate::git::{self, resolve_revision, Revision};
+use crate::cache::{self, Checkout};
+use crate::network;
+use crate::cmd::wrapper;
+use crate::index::{declarations, declares_module, defined_names, references, rename_modules, DeclKind, ModuleIndex};
 
 use crossterm::{
     cursor::{Hide, MoveTo, Show},
@@ -12,44 +18,51 @@ use crossterm::{
         LeaveAlternateScreen,
     },
 };
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env::current_dir;
-use std::path::{Path, PathBuf};
-use std::{fs, process::Command};
+use std::path::{Component, Path, PathBuf};
+use std::fs;
 use anyhow::{Context, Result};
 use parsv::{get_submodules, generate_headers};
-use walkdir::{DirEntry, WalkDir};
+use serde::Serialize;
+use walkdir::WalkDir;
 
 use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 use indicatif::{ProgressBar, ProgressStyle};
 
 impl Execute for Include {
     async fn execute(&self) -> Result<()> {
+        // Fail before downloading anything when there is no manifest to record the dependency in.
+        VpmToml::load("vpm.toml")?;
         fs::create_dir_all("./vpm_modules")?;
         println!("Including from: '{}'", self.url);
-        let repo_name = name_from_url(&self.url);
-        let mut is_head = false;
-        let tmp_path = PathBuf::from("/tmp").join(repo_name);
-        let commit = if self.commit.is_none() {
-            is_head = true;
-            Some(get_head_commit_hash(&self.url).context("Failed to get HEAD commit hash. Please check your internet connection and ensure the provided URL is correct.")?)
-        } else {
-            self.commit.clone()
-        };
-
-        let included_modules: HashSet<String> = if self.repo {
-            include_entire_repo(&self.url, &tmp_path, self.riscv, commit.as_deref(), is_head).context("Failed to include entire repository")?
-        } else {
-            include_single_module(&self.url, self.riscv, commit.as_deref(), is_head).context("Failed to include single module")?
+        let (repo_url, module_path) = parse_source(&self.url, self.path.as_deref(), self.repo || self.has_selection());
+        anyhow::ensure!(git::is_fetchable(&repo_url), "'{}' is not a git URL, an archive or a local directory", repo_url);
+        let prefix = self.prefix(&repo_url)?;
+        let revision = self.revision();
+        let commit = resolve_revision(&repo_url, &revision)
+            .with_context(|| format!("Failed to resolve {} of '{}'. Please check your internet connection and ensure the provided URL is correct.", revision, repo_url))?;
+
+        let repo = include_repo_from_url(&repo_url, &commit)?;
+
+        // Read once and written once, also recording what was copied before a failure.
+        let mut lockfile = Lockfile::load("vpm.lock")?;
+        let included = match &module_path {
+            Some(module_path) => include_single_module(module_path, &repo, self.riscv, &revision, prefix.as_deref(), &mut lockfile).context("Failed to include single module"),
+            None => include_entire_repo(&repo, self, &revision, prefix.as_deref(), &mut lockfile).context("Failed to include entire repository"),
         };
+        lockfile.save("vpm.lock")?;
+        let included_modules: HashSet<String> = included?;
+        report_duplicates()?;
+        wrapper::refresh()?;
 
         if self.with_docs {
             for module in included_modules {
                 let module_content = fs::read_to_string(&module).context("Failed to read module content")?;
                 let doc_path = Some(PathBuf::from(&module).with_extension("md"));
-                if self.offline {
+                if self.local_model || network::is_offline() {
                     generate_docs_offline(&module.to_string(), &module_content, doc_path).await.context("Failed to generate documentation offline")?;
                 } else {
                     generate_docs(&module.to_string(), &module_content, doc_path).await.context("Failed to generate documentation")?;
@@ -61,77 +74,98 @@ impl Execute for Include {
     }
 }
 
-pub fn get_head_commit_hash(url: &str) -> Result<String> {
-    let github_url = if url.starts_with("https://github.com/") {
-        url.to_string()
-    } else {
-        format!("https://github.com/{}", url)
-    };
-
-    let (repo_url, _) = github_url.rsplit_once("/blob/").unwrap_or((&github_url, ""));
+impl Include {
+    /// Whether the modules to include from the repository were chosen on the command line.
+    fn has_selection(&self) -> bool {
+        self.all || !self.select.is_empty() || !self.module.is_empty()
+    }
 
-    let output = Command::new("git")
-        .args(["ls-remote", repo_url, "HEAD"])
-        .output()?;
+    /// The prefix to namespace the included modules with: the one given to
+    /// `--namespace`, else the repository name made into an identifier.
+    fn prefix(&self, repo_url: &str) -> Result<Option<String>> {
+        let Some(prefix) = self.namespace.as_deref() else {
+            return Ok(None);
+        };
+        if !prefix.is_empty() {
+            anyhow::ensure!(
+                prefix.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                "--namespace must be a Verilog identifier, got '{}'", prefix
+            );
+            return Ok(Some(prefix.to_string()));
+        }
+        let mut prefix: String = name_from_url(repo_url).chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !prefix.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            prefix.insert(0, '_');
+        }
+        Ok(Some(prefix))
+    }
 
-    if output.status.success() {
-        let stdout = String::from_utf8(output.stdout)?;
-        let hash = stdout.split_whitespace().next().unwrap_or("").to_string();
-        if !hash.is_empty() {
-            Ok(hash[..7].to_string())  // Return only the first 7 characters (short hash)
+    fn revision(&self) -> Revision {
+        if let Some(tag) = &self.tag {
+            Revision::Tag(tag.clone())
+        } else if let Some(branch) = &self.branch {
+            Revision::Branch(branch.clone())
+        } else if let Some(version) = &self.version {
+            Revision::from_version(version)
+        } else if let Some(commit) = &self.commit {
+            Revision::Rev(commit.clone())
+        } else if let Some(sha256) = &self.sha256 {
+            Revision::Sha256(sha256.clone())
         } else {
-            Err(anyhow::anyhow!("Failed to get HEAD commit hash: Empty hash returned"))
+            Revision::Head
         }
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(anyhow::anyhow!("Failed to get HEAD commit hash: {}", stderr))
     }
 }
 
-fn include_entire_repo(url: &str, tmp_path: &PathBuf, riscv: bool, commit_hash: Option<&str>, is_head: bool) -> Result<HashSet<String>> {
-    let url = format!("https://github.com/{}", url);
-    println!("Full GitHub URL: {}@{}", url, commit_hash.unwrap_or("HEAD"));
-    include_repo_from_url(&url, "/tmp/", commit_hash, is_head)?;
-    add_dependency(&url)?;
+fn include_entire_repo(repo: &Checkout, include: &Include, revision: &Revision, prefix: Option<&str>, lockfile: &mut Lockfile) -> Result<HashSet<String>> {
+    println!("Repository: {}@{}", repo.url, repo.commit);
+    add_dependency(&repo.url)?;
 
-    let files = get_files(&tmp_path.to_str().unwrap_or_default());
-    let items = get_relative_paths(&files, tmp_path);
+    let files = get_files(repo.root.to_str().unwrap_or_default());
+    let items = get_relative_paths(&files, &repo.root);
 
-    let selected_items = select_modules(&items).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let selected_items = choose_modules(include, repo, &items)?;
 
-    process_selected_modules(&url, tmp_path, &selected_items, riscv, commit_hash, is_head)?;
+    process_selected_modules(repo, &selected_items, include.riscv, revision, prefix, lockfile)?;
 
-    fs::remove_dir_all(tmp_path)?;
-    print_success_message(&url, &selected_items);
+    print_success_message(&repo.url, &selected_items);
 
 
-    let mut included_modules = HashSet::new();
-    for item in selected_items {
-        let module_name_with_extension = item.split('/').last().unwrap_or(&item);
-        let module_name = module_name_with_extension.split('.').next().unwrap_or(module_name_with_extension);
-        let module_path = format!("vpm_modules/{module_name}/rtl/{module_name_with_extension}");
-        included_modules.insert(module_path);
-    }
+    let included_modules = selected_items.iter()
+        .map(|item| local_module_path(item, prefix))
+        .collect();
 
     Ok(included_modules)
 }
 
-fn include_single_module(url: &str, riscv: bool, commit_hash: Option<&str>, is_head: bool) -> Result<HashSet<String>> {
-    let repo_url = get_github_repo_url(url).unwrap();
-    include_repo_from_url(&repo_url, "/tmp/", commit_hash, is_head)?;
-    add_dependency(&repo_url)?;
-    println!("Repo URL: {}@{}", repo_url, commit_hash.unwrap_or("HEAD"));
-    let module_path = get_component_path_from_github_url(url).unwrap_or_default();
+fn include_single_module(module_path: &str, repo: &Checkout, riscv: bool, revision: &Revision, prefix: Option<&str>, lockfile: &mut Lockfile) -> Result<HashSet<String>> {
+    anyhow::ensure!(repo.root.join(module_path).is_file(), "'{}' does not exist in {}", module_path, repo.url);
+    add_dependency(&repo.url)?;
+    println!("Repo URL: {}@{}", repo.url, repo.commit);
     println!("Including module: {}", module_path);
-    include_module_from_url(&module_path, &repo_url, riscv, commit_hash, is_head)?;
+    include_module_from_url(module_path, repo, riscv, revision, prefix, lockfile)?;
     println!("Successfully installed module: {}", module_path);
 
-    let mut included_modules = HashSet::new(); 
-    let module_name_with_extension = module_path.split('/').last().unwrap_or(&module_path);
-    let module_name = module_name_with_extension.split('.').next().unwrap_or(module_name_with_extension);
-    let local_module_path = format!("vpm_modules/{module_name}/rtl/{module_name_with_extension}");
-    included_modules.insert(local_module_path);
-    Ok(included_modules)
+    Ok(HashSet::from([local_module_path(module_path, prefix)]))
+}
+
+/// Where the top module `module_path` of the repository is copied to:
+/// `vpm_modules/<stem>/rtl/<file>`, with both names prefixed under `--namespace`.
+fn local_module_path(module_path: &str, prefix: Option<&str>) -> String {
+    let file_name = module_path.split('/').next_back().unwrap_or(module_path);
+    let stem = file_name.split('.').next().unwrap_or(file_name);
+    format!("vpm_modules/{}/rtl/{}", local_name(stem, prefix), local_name(file_name, prefix))
+}
+
+/// The name an upstream module, or the file holding it, gets in the project:
+/// with `--namespace`, the prefix is added unless the name already has it.
+fn local_name(name: &str, prefix: Option<&str>) -> String {
+    match prefix {
+        Some(prefix) if !name.starts_with(&format!("{}_", prefix)) => format!("{}_{}", prefix, name),
+        _ => name.to_string(),
+    }
 }
 
 fn get_files(directory: &str) -> Vec<String> {
@@ -149,27 +183,174 @@ fn get_files(directory: &str) -> Vec<String> {
         .collect()
 }
 
-fn get_relative_paths(files: &[String], tmp_path: &PathBuf) -> Vec<String> {
+fn get_relative_paths(files: &[String], tmp_path: &Path) -> Vec<String> {
     files.iter()
-        .map(|file| file.strip_prefix(&tmp_path.to_string_lossy().as_ref())
+        .map(|file| file.strip_prefix(tmp_path.to_string_lossy().as_ref())
             .unwrap_or(file)
             .trim_start_matches('/')
             .to_string())
         .collect()
 }
 
-fn select_modules(items: &[String]) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
+fn is_hdl_file(path: &str) -> bool {
+    path.ends_with(".v") || path.ends_with(".sv")
+}
+
+/// The files of the repository to include: the ones picked with --select,
+/// --module or --all, or else the ones chosen in the picker. Without a terminal
+/// to run the picker on, one of the flags is required.
+fn choose_modules(include: &Include, repo: &Checkout, items: &[String]) -> Result<HashSet<String>> {
+    let hdl_files: Vec<&String> = items.iter().filter(|item| is_hdl_file(item)).collect();
+    if include.all {
+        return Ok(hdl_files.into_iter().cloned().collect());
+    }
+    if include.select.is_empty() && include.module.is_empty() {
+        anyhow::ensure!(
+            io::stdin().is_terminal(),
+            "Input is not a terminal, so the modules to include cannot be picked interactively. Choose them with --select <GLOB>, --module <NAME> or --all."
+        );
+        return select_modules(repo, items).map_err(|e| anyhow::anyhow!("{}", e));
+    }
+
+    let mut selected = HashSet::new();
+    for pattern in &include.select {
+        let matched: Vec<&&String> = hdl_files.iter().filter(|item| glob_match(pattern, item)).collect();
+        anyhow::ensure!(!matched.is_empty(), "--select '{}' matches no .v or .sv file in {}", pattern, repo.url);
+        selected.extend(matched.into_iter().map(|item| item.to_string()));
+    }
+    for name in &include.module {
+        let declarations = repo.index().lookup(name, DeclKind::Module);
+        match declarations.as_slice() {
+            [] => anyhow::bail!("No file in {} declares module '{}'", repo.url, name),
+            [declaration] => {
+                selected.insert(lock_path(&declaration.path));
+            }
+            _ => anyhow::bail!(
+                "Module '{}' is declared in several files of {}: {}. Choose one with --select <PATH>.",
+                name, repo.url,
+                declarations.iter().map(|d| lock_path(&d.path)).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+    Ok(selected)
+}
+
+/// Whether `path` matches `pattern`, where `*` and `?` match within a path
+/// component and `**` matches across components. A pattern without `/` is
+/// matched against the file name only, as in .gitignore.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[u8], path: &[u8]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            [b'*', b'*', rest @ ..] => match rest.strip_prefix(b"/") {
+                // `**/` matches zero or more whole directories.
+                Some(rest) => (0..=path.len()).any(|i| (i == 0 || path[i - 1] == b'/') && matches(rest, &path[i..])),
+                None => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+            },
+            [b'*', rest @ ..] => (0..=path.len())
+                .take_while(|&i| i == 0 || path[i - 1] != b'/')
+                .any(|i| matches(rest, &path[i..])),
+            [b'?', rest @ ..] => path.first().is_some_and(|&c| c != b'/') && matches(rest, &path[1..]),
+            [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..]),
+        }
+    }
+    let path = if pattern.contains('/') { path } else { path.rsplit('/').next().unwrap_or(path) };
+    matches(pattern.as_bytes(), path.as_bytes())
+}
+
+/// A module instantiated by a file, in the tree of modules the file pulls in.
+#[derive(Debug, Serialize)]
+pub struct Submodule {
+    pub name: String,
+    /// File of the repository declaring the module; `None` if no file does.
+    pub path: Option<PathBuf>,
+    /// The file was reached earlier in the tree, which lists its submodules.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub repeated: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub submodules: Vec<Submodule>,
+}
+
+/// The modules instantiated in `contents`, resolved transitively through the
+/// module index of `repo`. Every file reached is added to `files` with its
+/// line count.
+pub fn submodule_tree(repo: &Checkout, contents: &str, files: &mut BTreeMap<PathBuf, usize>) -> Vec<Submodule> {
+    if !declares_module(contents) {
+        return Vec::new();
+    }
+    let defined = defined_names(contents);
+    let mut names: Vec<String> = get_submodules(contents).unwrap_or_default().into_iter()
+        .filter(|s| !defined.contains(s))
+        .collect();
+    names.sort();
+    names.into_iter().map(|name| {
+        let path = repo.index().lookup(&name, DeclKind::Module).first().map(|d| d.path.clone());
+        let Some(path) = path else {
+            return Submodule { name, path: None, repeated: false, submodules: Vec::new() };
+        };
+        if files.contains_key(&path) {
+            return Submodule { name, path: Some(path), repeated: true, submodules: Vec::new() };
+        }
+        let contents = fs::read_to_string(repo.root.join(&path)).unwrap_or_default();
+        files.insert(path.clone(), contents.lines().count());
+        let submodules = submodule_tree(repo, &contents, files);
+        Submodule { name, path: Some(path), repeated: false, submodules }
+    }).collect()
+}
+
+/// Draws `tree` one submodule per line, with box-drawing branches.
+pub fn draw_tree(tree: &[Submodule], prefix: &str, lines: &mut Vec<String>) {
+    for (i, submodule) in tree.iter().enumerate() {
+        let last = i + 1 == tree.len();
+        let location = match (&submodule.path, submodule.repeated) {
+            (None, _) => "not in the repository".to_string(),
+            (Some(path), false) => path.display().to_string(),
+            (Some(path), true) => format!("{}, see above", path.display()),
+        };
+        lines.push(format!("{}{}{} ({})", prefix, if last { "└─ " } else { "├─ " }, submodule.name, location));
+        draw_tree(&submodule.submodules, &format!("{}{}", prefix, if last { "   " } else { "│  " }), lines);
+    }
+}
+
+/// What the picker shows for the highlighted file, and what including it adds.
+struct Preview {
+    /// Module header (ports and parameters) of the file.
+    header: Vec<String>,
+    /// The submodules the file pulls in, transitively, drawn as a tree.
+    tree: Vec<String>,
+    /// Every file the file pulls in, itself included, with its line count.
+    files: BTreeMap<PathBuf, usize>,
+}
+
+impl Preview {
+    fn new(repo: &Checkout, item: &str) -> Self {
+        let path = PathBuf::from(item);
+        let contents = fs::read_to_string(repo.root.join(&path)).unwrap_or_default();
+        let header = match generate_headers(&contents) {
+            Ok(header) => header.lines().map(|line| line.replace('\t', "    ")).collect(),
+            Err(e) => vec![format!("(no module header: {})", e)],
+        };
+        let mut files = BTreeMap::from([(path, contents.lines().count())]);
+        let mut tree = vec![item.to_string()];
+        draw_tree(&submodule_tree(repo, &contents, &mut files), "", &mut tree);
+        Preview { header, tree, files }
+    }
+}
+
+/// Pads or cuts `text` to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    format!("{:<width$}", text.chars().take(width).collect::<String>(), width = width)
+}
+
+fn select_modules(repo: &Checkout, items: &[String]) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
     fn filter_items<'a>(items: &'a [String], matcher: &SkimMatcherV2, query: &str) -> Vec<&'a String> {
         let mut filtered: Vec<&String> = items
             .iter()
-            .filter(|item| item.ends_with(".v") || item.ends_with(".sv"))
+            .filter(|item| is_hdl_file(item))
             .collect();
     
         if !query.is_empty() {
-            filtered = filtered
-                .into_iter()
-                .filter(|&item| matcher.fuzzy_match(item, query).is_some())
-                .collect();
+            filtered.retain(|&item| matcher.fuzzy_match(item, query).is_some());
         }
     
         filtered
@@ -177,6 +358,10 @@ fn select_modules(items: &[String]) -> Result<HashSet<String>, Box<dyn std::erro
 
     let matcher = SkimMatcherV2::default();
     let mut selected_items: HashSet<String> = HashSet::new();
+    // Previews are computed once per file, when it is first highlighted.
+    let mut previews: HashMap<String, Preview> = HashMap::new();
+    // Indexing prints progress, which must happen before the screen is taken over.
+    repo.index();
 
     // Initialize terminal
     let mut stdout = io::stdout();
@@ -196,33 +381,63 @@ fn select_modules(items: &[String]) -> Result<HashSet<String>, Box<dyn std::erro
         // Clear the screen and reset cursor position
         execute!(stdout, MoveTo(0, 0), Clear(ClearType::All))?;
 
-        // Display the prompt and query
+        let (width, height) = crossterm::terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let list_width = (width / 2).max(30).min(width.saturating_sub(3));
+        let preview_width = width.saturating_sub(list_width + 3);
+        let list_height = height.saturating_sub(4);
+
+        // Files and lines all selected modules add, counting shared submodules once.
+        for item in selected_items.iter().chain(filtered_items.get(current_selection).copied()) {
+            previews.entry(item.clone()).or_insert_with(|| Preview::new(repo, item));
+        }
+        let added: BTreeMap<&PathBuf, usize> = selected_items.iter()
+            .flat_map(|item| &previews[item].files)
+            .map(|(path, lines)| (path, *lines))
+            .collect();
+
+        write!(stdout, "{}\r\n", fit(&format!("Enter module name (Enter to select, Esc to finish): {}", query), width))?;
         write!(
             stdout,
-            "Enter module name (or press Esc to finish): {}\r\n",
-            query
+            "{}\r\n{}\r\n",
+            fit(&format!("{} selected: {} file(s), {} line(s) to add to vpm_modules/", selected_items.len(), added.len(), added.values().sum::<usize>()), width),
+            "─".repeat(width)
         )?;
-        if !selected_items.is_empty() {
-            write!(stdout, "Selected modules:\r\n{}\r\n\r\n", selected_items.iter().map(|item| format!("\t- {}", item)).collect::<Vec<String>>().join("\r\n"))?;
+
+        let mut preview_lines = Vec::new();
+        if let Some(preview) = filtered_items.get(current_selection).map(|item| &previews[*item]) {
+            preview_lines.push(format!("Adds {} file(s), {} line(s)", preview.files.len(), preview.files.values().sum::<usize>()));
+            preview_lines.push(String::new());
+            preview_lines.extend(preview.header.iter().cloned());
+            preview_lines.push(String::new());
+            preview_lines.push("Submodules:".to_string());
+            preview_lines.extend(preview.tree.iter().cloned());
         }
 
-        // Get terminal size
-        let (_, height) = crossterm::terminal::size()?;
-        let max_display = (height - 4 - selected_items.len() as u16) as usize; // Subtract 3 for prompt and margins
-
-        // Display the filtered items with selection highlighting
-        for (index, item) in filtered_items.iter().take(max_display).enumerate() {
-            if index == current_selection {
-                execute!(
-                    stdout,
-                    SetBackgroundColor(Color::Blue),
-                    SetForegroundColor(Color::White),
-                    Print(format!("> {}\r\n", item)),
-                    ResetColor
-                )?;
-            } else {
-                write!(stdout, "  {}\r\n", item)?;
+        // Scroll the list so that the highlighted file stays visible.
+        let first = current_selection.saturating_sub(list_height.saturating_sub(1));
+        for row in 0..list_height {
+            let index = first + row;
+            let preview = fit(preview_lines.get(row).map(String::as_str).unwrap_or(""), preview_width);
+            match filtered_items.get(index) {
+                Some(item) => {
+                    let mark = if selected_items.contains(*item) { "[x]" } else { "[ ]" };
+                    let line = fit(&format!("{} {} {}", if index == current_selection { ">" } else { " " }, mark, item), list_width);
+                    if index == current_selection {
+                        execute!(
+                            stdout,
+                            SetBackgroundColor(Color::Blue),
+                            SetForegroundColor(Color::White),
+                            Print(line),
+                            ResetColor
+                        )?;
+                    } else {
+                        write!(stdout, "{}", line)?;
+                    }
+                }
+                None => write!(stdout, "{}", fit("", list_width))?,
             }
+            write!(stdout, " │ {}\r\n", preview)?;
         }
 
         stdout.flush()?;
@@ -241,25 +456,22 @@ fn select_modules(items: &[String]) -> Result<HashSet<String>, Box<dyn std::erro
                     current_selection = 0;
                 }
                 KeyCode::Up => {
-                    if current_selection > 0 {
-                        current_selection -= 1;
-                    }
+                    current_selection = current_selection.saturating_sub(1);
                 }
-                KeyCode::Down => {
-                    if current_selection + 1 < filtered_items.len().min(max_display) {
-                        current_selection += 1;
-                    }
+                KeyCode::Down if current_selection + 1 < filtered_items.len() => {
+                    current_selection += 1;
                 }
-                KeyCode::Enter => {
-                    if !filtered_items.is_empty() {
-                        let selected = filtered_items[current_selection].clone();
-                        selected_items.insert(selected.to_string());
-
-                        query.clear();
-                        filtered_items = filter_items(items, &matcher, &query);
-                        current_selection = 0;
-                        continue;
+                KeyCode::Enter if !filtered_items.is_empty() => {
+                    let selected = filtered_items[current_selection].clone();
+                    // Enter on a selected file deselects it.
+                    if !selected_items.remove(&selected) {
+                        selected_items.insert(selected);
                     }
+
+                    query.clear();
+                    filtered_items = filter_items(items, &matcher, &query);
+                    current_selection = 0;
+                    continue;
                 }
                 KeyCode::Esc => {
                     break;
@@ -281,7 +493,8 @@ fn select_modules(items: &[String]) -> Result<HashSet<String>, Box<dyn std::erro
     Ok(selected_items)
 }
 
-fn process_selected_modules(url: &str, tmp_path: &PathBuf, selected_items: &HashSet<String>, riscv: bool, commit_hash: Option<&str>, is_head: bool) -> Result<()> {
+fn process_selected_modules(repo: &Checkout, selected_items: &HashSet<String>, riscv: bool, revision: &Revision, prefix: Option<&str>, lockfile: &mut Lockfile) -> Result<()> {
+    let tmp_path = &repo.root;
     for item in selected_items {
         let displayed_path = item.strip_prefix(tmp_path.to_string_lossy().as_ref()).unwrap_or(item).trim_start_matches('/');
         println!("Including module: {}", displayed_path);
@@ -290,12 +503,13 @@ fn process_selected_modules(url: &str, tmp_path: &PathBuf, selected_items: &Hash
         let module_path = full_path.strip_prefix(tmp_path).unwrap_or(&full_path).to_str().unwrap().trim_start_matches('/');
         println!("Module path: {}", module_path);
 
-        include_module_from_url(module_path, url, riscv, commit_hash, is_head)?;
+        include_module_from_url(module_path, repo, riscv, revision, prefix, lockfile)?;
     }
 
     if selected_items.is_empty() {
         println!("No modules selected. Including entire repository.");
-        include_repo_from_url(url, "./vpm_modules/", commit_hash, is_head)?;
+        let destination = Path::new("./vpm_modules").join(name_from_url(&repo.url));
+        copy_repo(tmp_path, &destination)?;
     }
 
     Ok(())
@@ -314,41 +528,36 @@ fn print_success_message(url: &str, selected_items: &HashSet<String>) {
 }
 
 pub fn name_from_url(url: &str) -> &str {
-    url.rsplit('/').find(|&s| !s.is_empty()).unwrap_or_default()
-}
-
-fn get_component_path_from_github_url(url: &str) -> Option<String> {
-    let parts: Vec<&str> = url.split("/").collect();
-    if parts.len() < 8 || !url.starts_with("https://github.com/") {
-        return None;
-    }
-
-    Some(parts[7..].join("/"))
+    let name = url.rsplit(['/', ':']).find(|&s| !s.is_empty()).unwrap_or_default();
+    name.strip_suffix(".git").unwrap_or(name)
 }
 
-fn get_github_repo_url(url: &str) -> Option<String> {
-    let parts: Vec<&str> = url.split('/').collect();
-    if parts.len() < 5 || !url.starts_with("https://github.com/") {
-        return None;
+/// Splits what was passed to `vpm include` into the source to fetch and the
+/// path of the module inside it, if one was given. Links to a file in a web
+/// UI (`<repo>/blob/<ref>/<path>` on GitHub, `<repo>/-/blob/<ref>/<path>` on
+/// GitLab) are still understood; with `--repo`, `AUTHOR/REPO` is a GitHub shorthand.
+pub fn parse_source(url: &str, path: Option<&str>, repo_shorthand: bool) -> (String, Option<String>) {
+    let url = url.trim_end_matches('/');
+    if path.is_none() && (url.starts_with("https://") || url.starts_with("http://")) {
+        for marker in ["/-/blob/", "/blob/"] {
+            if let Some((repo_url, rest)) = url.split_once(marker) {
+                if let Some((_, file)) = rest.split_once('/') {
+                    return (repo_url.to_string(), Some(file.to_string()));
+                }
+            }
+        }
     }
-
-    Some(format!("https://github.com/{}/{}", parts[3], parts[4]))
+    let is_shorthand = repo_shorthand
+        && !git::is_fetchable(url)
+        && url.split('/').filter(|s| !s.is_empty()).count() == 2;
+    let repo_url = if is_shorthand { format!("https://github.com/{}", url) } else { url.to_string() };
+    (repo_url, path.map(|p| p.trim_start_matches('/').to_string()))
 }
 
 fn is_full_filepath(path: &str) -> bool {
     path.contains('/') || path.contains('\\')
 }
 
-fn filepath_to_dir_entry(filepath: PathBuf) -> Result<DirEntry> {
-    WalkDir::new(filepath)
-        .min_depth(0)
-        .max_depth(0)
-        .into_iter()
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Failed to create DirEntry"))?
-        .context("Failed to create DirEntry")
-}
-
 fn generate_top_v_content(module_path: &str) -> Result<String> {
     println!("Generating top.v file for RISC-V in {}", module_path);
     let module_content = fs::read_to_string(module_path)?;
@@ -447,8 +656,7 @@ fn generate_xdc_content(module_path: &str) -> Result<String> {
     // Generate constraints for each port
     for (_port_type, port_name) in ports {
         if let Some((_, pin)) = pin_mappings.iter().find(|&&(p, _)| p == port_name) {
-            let iostandard = if port_name == "clk" { "LVCMOS33" } else { "LVCMOS33" };
-            xdc_content.push_str(&format!("set_property -dict {{ PACKAGE_PIN {} IOSTANDARD {} }} [get_ports {{ {} }}]\n", pin, iostandard, port_name));
+            xdc_content.push_str(&format!("set_property -dict {{ PACKAGE_PIN {} IOSTANDARD LVCMOS33 }} [get_ports {{ {} }}]\n", pin, port_name));
         } else {
             println!("Warning: No pin mapping found for port: {}", port_name);
         }
@@ -456,8 +664,8 @@ fn generate_xdc_content(module_path: &str) -> Result<String> {
 
     // Add clock constraint
     if let Some((_, _clk_pin)) = pin_mappings.iter().find(|&&(p, _)| p == "clk") {
-        xdc_content.push_str(&format!("\n## Clock signal\n"));
-        xdc_content.push_str(&format!("create_clock -period 10.000 -name sys_clk_pin -waveform {{0.000 5.000}} -add [get_ports {{ clk }}]\n"));
+        xdc_content.push_str("\n## Clock signal\n");
+        xdc_content.push_str("create_clock -period 10.000 -name sys_clk_pin -waveform {0.000 5.000} -add [get_ports { clk }]\n");
     } else {
         println!("Warning: No clock signal found. XDC file may be incomplete.");
         xdc_content.push_str("\n## Warning: No clock signal found. Please add clock constraints manually.\n");
@@ -466,23 +674,24 @@ fn generate_xdc_content(module_path: &str) -> Result<String> {
     Ok(xdc_content)
 }
 
-pub fn include_module_from_url(module_path: &str, url: &str, riscv: bool, commit_hash: Option<&str>, is_head: bool) -> Result<()> {
-    let package_name = name_from_url(url);
-
-    include_repo_from_url(url, "/tmp/", commit_hash, is_head)?;
+pub fn include_module_from_url(module_path: &str, repo: &Checkout, riscv: bool, revision: &Revision, prefix: Option<&str>, lockfile: &mut Lockfile) -> Result<()> {
     let module_name = Path::new(module_path)
         .file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or(module_path);
-    let destination = format!("./vpm_modules/{}/rtl", module_name);
+    let destination = format!("./vpm_modules/{}/rtl", local_name(module_name, prefix));
     fs::create_dir_all(&destination)?;
-    process_module(package_name, module_path, destination.to_owned(), &mut HashSet::new(), url, true, commit_hash)?;
+    let mut walk = Walk::new(lockfile, prefix);
+    process_module(repo, module_path, destination.to_owned(), &mut walk, true, None)?;
 
-    let module_path = Path::new(&destination).join(Path::new(module_path).file_name().unwrap());
+    let upstream_path = Path::new(module_path);
+    let file_name = upstream_path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+    let module_path = Path::new(&destination).join(local_name(file_name, prefix));
     anyhow::ensure!(module_path.exists(), "Module file not found in the destination folder");
+    include_top_assets(repo, upstream_path, &module_path, &mut walk)?;
 
     if riscv {
-        let top_v_content = generate_top_v_content(&module_path.to_str().unwrap())?;
+        let top_v_content = generate_top_v_content(module_path.to_str().unwrap())?;
         fs::write(format!("{}/top.v", destination), top_v_content)?;
         println!("Created top.v file for RISC-V in {}", destination);
         // Generate .xdc file for Xilinx Artix-7 board
@@ -490,294 +699,498 @@ pub fn include_module_from_url(module_path: &str, url: &str, riscv: bool, commit
         fs::write(format!("{}/constraints.xdc", destination), xdc_content)?;
         println!("Created constraints.xdc file for Xilinx Artix-7 board in {}", destination);
     }
-    add_top_module(url, current_dir()?.join(module_path.file_name().unwrap()).to_str().unwrap(), commit_hash.unwrap_or(""))?;
-    
+    let pinned = repo.pinned(revision);
+    add_top_module(&repo.url, current_dir()?.join(module_path.file_name().unwrap()).to_str().unwrap(), &pinned)?;
+    if let Some(entry) = lockfile.get_mut(&lock_path(&module_path)) {
+        entry.revision = pinned.to_string();
+    }
+
     Ok(())
 }
 
-pub fn process_module(package_name: &str, module: &str, destination: String, visited: &mut HashSet<String>, url: &str, is_top_module: bool, commit_hash: Option<&str>) -> Result<HashSet<String>> {
-    // println!("Processing module: {}", module);
+/// Records in vpm.lock the revision vpm.toml asks for of the top module at `module_path`.
+pub fn record_revision(module_path: &Path, revision: &Revision) -> Result<()> {
+    with_lockfile(|lockfile| {
+        if let Some(entry) = lockfile.get_mut(&lock_path(module_path)) {
+            entry.revision = revision.to_string();
+        }
+        Ok(())
+    })
+}
+
+/// State of one recursive include walk.
+pub struct Walk<'a> {
+    /// vpm.lock as loaded by the include; every copied file is recorded here.
+    lockfile: &'a mut Lockfile,
+    /// Upstream paths already copied, with the lockfile key of their copy.
+    copied: HashMap<PathBuf, String>,
+    /// Declarations already resolved to an upstream file, so every name is resolved (and asked about) once.
+    resolved: HashMap<(DeclKind, String), Option<PathBuf>>,
+    /// With `--namespace`, what to prefix the repository's module names with.
+    prefix: Option<String>,
+}
+
+impl<'a> Walk<'a> {
+    pub fn new(lockfile: &'a mut Lockfile, prefix: Option<&str>) -> Self {
+        Walk { lockfile, copied: HashMap::new(), resolved: HashMap::new(), prefix: prefix.map(ToString::to_string) }
+    }
+}
+
+/// Copies `module` and, recursively, everything it depends on into
+/// `destination`. `module` is either a path inside the repository or a module
+/// name, which is resolved through the repository's module index.
+pub fn process_module(repo: &Checkout, module: &str, destination: String, walk: &mut Walk, is_top_module: bool, parent: Option<&str>) -> Result<HashSet<String>> {
     let module_name = module.strip_suffix(".v").or_else(|| module.strip_suffix(".sv")).unwrap_or(module);
-    let module_with_ext = if module.ends_with(".v") || module.ends_with(".sv") {
-        module.to_string()
+    let module_name = Path::new(module_name).file_name().and_then(|s| s.to_str()).unwrap_or(module_name);
+    let is_path = module.ends_with(".v") || module.ends_with(".sv");
+    let upstream_path = if is_path && (is_top_module || is_full_filepath(module)) && repo.root.join(module).is_file() {
+        PathBuf::from(module)
     } else {
-        format!("{}.v", module_name)
+        match resolve_declaration(repo, DeclKind::Module, module_name, walk)? {
+            Some(path) => path,
+            None => {
+                println!("No declaration found for module '{}'. Skipping...", module_name);
+                return Ok(HashSet::new());
+            }
+        }
     };
-    if !visited.insert(module_with_ext.clone()) {
-        return Ok(HashSet::new());
-    }
-
-    let tmp_path = PathBuf::from("/tmp").join(package_name);
-    let file_path = tmp_path.join(&module_with_ext);
 
-    let target_path = PathBuf::from(&destination);
+    let file_name = upstream_path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+    let local_path = PathBuf::from(&destination).join(local_name(file_name, walk.prefix.as_deref()));
+    process_dependency(repo, &upstream_path, &local_path, &destination, walk, parent)
+}
 
-    println!("Including submodule '{}'", module_with_ext);
+/// Copies one upstream file to `local_path` and follows its submodules,
+/// packages, interfaces and `` `include `` files.
+fn process_dependency(repo: &Checkout, upstream_path: &Path, local_path: &Path, destination: &str, walk: &mut Walk, parent: Option<&str>) -> Result<HashSet<String>> {
+    if let Some(full_path) = walk.copied.get(upstream_path) {
+        // Already copied during this walk; only record the additional parent.
+        if let Some(parent) = parent {
+            walk.lockfile.add_parent(full_path, parent);
+        }
+        return Ok(HashSet::new());
+    }
+    let full_path = lock_path(local_path);
+    claim_destination(repo, upstream_path, &full_path, walk)?;
+
+    println!("Including submodule '{}'", lock_path(upstream_path));
+    let contents = fs::read_to_string(repo.root.join(upstream_path))
+        .with_context(|| format!("Failed to read '{}' from {}", upstream_path.display(), repo.url))?;
+    let dependencies = Dependencies::scan(repo.index(), &contents);
+    let renames = walk.prefix.as_deref().map(|prefix| namespace(repo, &contents, prefix)).unwrap_or_default();
+    if !renames.is_empty() {
+        println!("Renaming {}", renames.iter().map(|(from, to)| format!("'{}' to '{}'", from, to)).collect::<Vec<_>>().join(", "));
+    }
+    let contents = rename_modules(&contents, &renames);
+    process_file(upstream_path, local_path, &contents, &dependencies, &LockSource { repo, parent, renames: &renames }, walk.lockfile)?;
 
-    let mut processed_modules = HashSet::new();
+    let mut processed_modules = HashSet::from([full_path.clone()]);
+    let parent = Some(full_path.as_str());
+    let mut collect = |name: &str, result: Result<HashSet<String>>| -> Result<()> {
+        processed_modules.extend(result.with_context(|| format!("Failed to include dependency '{}' of '{}'", name, lock_path(upstream_path)))?);
+        Ok(())
+    };
 
-    if is_full_filepath(&module_with_ext) {
-        // println!("Full filepath detected for module '{}'", module_with_ext);
-        let dir_entry = filepath_to_dir_entry(file_path)?;
-        // println!("Dir entry: {}", dir_entry.path().display());
-        process_file(&dir_entry, &target_path.to_str().unwrap(), module, url, visited, is_top_module)?;
-        processed_modules.insert(module_with_ext.clone());
-    } else {
-        // println!("Full filepath not detected for module '{}'", module_with_ext);
-        process_non_full_filepath(module_name, &tmp_path, &target_path, url, visited, is_top_module, &mut processed_modules)?;
+    for submodule in &dependencies.submodules {
+        collect(submodule, process_module(repo, submodule, destination.to_string(), walk, false, parent))?;
+    }
+    for (kind, names) in [(DeclKind::Package, &dependencies.packages), (DeclKind::Interface, &dependencies.interfaces)] {
+        for name in names {
+            let result = resolve_declaration(repo, kind, name, walk).and_then(|path| match path {
+                Some(path) => {
+                    let local = Path::new(destination).join(path.file_name().unwrap());
+                    process_dependency(repo, &path, &local, destination, walk, parent)
+                }
+                None => Ok(HashSet::new()),
+            });
+            collect(name, result)?;
+        }
+    }
+    for include in &dependencies.includes {
+        let Some(path) = resolve_file(repo, upstream_path, include) else {
+            println!("Warning: `include \"{}\" in '{}' was not found in {}. Skipping...", include, lock_path(upstream_path), repo.url);
+            continue;
+        };
+        // Keep the path as written so the directive still resolves next to the including file.
+        let local_dir = local_path.parent().unwrap_or(Path::new("."));
+        let local = if Path::new(include).components().all(|c| matches!(c, Component::Normal(_))) {
+            local_dir.join(include)
+        } else {
+            local_dir.join(path.file_name().unwrap())
+        };
+        collect(include, process_dependency(repo, &path, &local, destination, walk, parent))?;
+    }
+    for data_file in &dependencies.assets {
+        let Some(path) = resolve_file(repo, upstream_path, data_file) else {
+            println!("Warning: data file \"{}\" of '{}' was not found in {}. Skipping...", data_file, lock_path(upstream_path), repo.url);
+            continue;
+        };
+        // Memory images are opened relative to the simulator's working directory,
+        // so they go to the module's root folder rather than next to the RTL.
+        let asset_dir = Path::new(destination).parent().unwrap_or(Path::new(destination));
+        let local = if Path::new(data_file).components().all(|c| matches!(c, Component::Normal(_))) {
+            asset_dir.join(data_file)
+        } else {
+            asset_dir.join(path.file_name().unwrap())
+        };
+        if let Err(e) = process_asset(repo, &path, &local, walk, parent) {
+            eprintln!("Warning: Failed to copy data file {}: {}. Skipping this file.", data_file, e);
+        }
     }
-
-    let submodules = download_and_process_submodules(package_name, module, &destination, url, visited, is_top_module, commit_hash)?;
-    processed_modules.extend(submodules);
 
     Ok(processed_modules)
 }
 
-fn process_non_full_filepath(module_name: &str, tmp_path: &PathBuf, target_path: &PathBuf, url: &str, visited: &mut HashSet<String>, is_top_module: bool, processed_modules: &mut HashSet<String>) -> Result<()> {
-    let matching_entries = find_matching_entries(module_name, tmp_path);
-    println!("Found {} matching entries for module '{}'", matching_entries.len(), module_name);
-    if matching_entries.is_empty() {
-        println!("No matching files found for module '{}'. Skipping...", module_name);
-    } else if matching_entries.len() == 1 {
-        let dir_entry = filepath_to_dir_entry(matching_entries[0].clone())?;
-        process_file(&dir_entry, target_path.to_str().unwrap(), module_name, url, visited, is_top_module)?;
-        processed_modules.insert(format!("{}.v", module_name));
-    } else {
-        process_multiple_matches(matching_entries, target_path, module_name, url, visited, is_top_module, processed_modules)?;
-    }
+const CONSTRAINT_EXTENSIONS: [&str; 6] = ["xdc", "sdc", "pcf", "lpf", "qsf", "tcl"];
 
-    Ok(())
-}
+/// Copies the constraint files next to the top module upstream, and the extra
+/// `assets` listed for it in vpm.toml, into the module's folder.
+fn include_top_assets(repo: &Checkout, upstream_path: &Path, module_path: &Path, walk: &mut Walk) -> Result<()> {
+    let parent = lock_path(module_path);
+    let asset_dir = module_path.parent().and_then(Path::parent).unwrap_or(Path::new("."));
+    let upstream_dir = upstream_path.parent().unwrap_or(Path::new(""));
 
-fn find_matching_entries(module_name: &str, tmp_path: &PathBuf) -> Vec<PathBuf> {
-    WalkDir::new(tmp_path)
-        .into_iter()
+    let mut siblings: Vec<PathBuf> = fs::read_dir(repo.root.join(upstream_dir)).into_iter().flatten()
         .filter_map(Result::ok)
-        .filter(|entry| {
-            entry.file_name().to_str() == Some(&format!("{}.sv", module_name)) || 
-            entry.file_name().to_str() == Some(&format!("{}.v", module_name))
-        })
-        .map(|entry| entry.path().to_path_buf())
-        .collect()
-}
-
-fn process_multiple_matches(matching_entries: Vec<PathBuf>, target_path: &PathBuf, module_name: &str, url: &str, visited: &mut HashSet<String>, is_top_module: bool, processed_modules: &mut HashSet<String>) -> Result<()> {
-    println!("Multiple modules found for '{}'. Please choose:", module_name);
-    for (i, entry) in matching_entries.iter().enumerate() {
-        println!("{}: {}", i + 1, entry.display());
+        .filter(|e| e.path().is_file())
+        .filter(|e| e.path().extension().and_then(|ext| ext.to_str()).is_some_and(|ext| CONSTRAINT_EXTENSIONS.contains(&ext)))
+        .map(|e| upstream_dir.join(e.file_name()))
+        .collect();
+    siblings.sort();
+
+    let listed = get_assets(&repo.url, &module_path.to_string_lossy())?;
+    for asset in listed {
+        anyhow::ensure!(repo.root.join(&asset).is_file(), "Asset '{}' listed in vpm.toml does not exist in {}@{}", asset, repo.url, repo.commit);
+        siblings.push(PathBuf::from(asset));
     }
 
-    let mut choice = String::new();
-    std::io::stdin().read_line(&mut choice)?;
-    let index: usize = choice.trim().parse()?;
-
-    if index > 0 && index <= matching_entries.len() {
-        let dir_entry = filepath_to_dir_entry(matching_entries[index - 1].clone())?;
-        process_file(&dir_entry, target_path.to_str().unwrap(), module_name, url, visited, is_top_module)?;
-        processed_modules.insert(format!("{}.v", module_name));
-    } else {
-        anyhow::bail!("Invalid choice");
+    for path in siblings {
+        let local = asset_dir.join(path.file_name().unwrap());
+        process_asset(repo, &path, &local, walk, Some(&parent))?;
     }
-
     Ok(())
 }
 
-fn process_file(entry: &DirEntry, destination: &str, module_path: &str, url: &str, visited: &mut HashSet<String>, is_top_module: bool) -> Result<()> {
-    let target_path = PathBuf::from(destination);
-    let extension = entry.path().extension().and_then(|s| s.to_str()).unwrap_or("v");
-    fs::copy(entry.path(), &target_path.join(entry.file_name()))?;
-
-    let contents = fs::read_to_string(entry.path())?;
-    let header_content = generate_headers(&contents)?;
-    let module_name = Path::new(module_path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or(module_path);
-    
-    let module_name_with_ext = if !module_name.ends_with(".v") && !module_name.ends_with(".sv") {
-        format!("{}.{}", module_name, extension)
-    } else {
-        module_name.to_string()
-    };
-    let header_filename = format!("{}.{}", module_name.strip_suffix(".v").unwrap_or(module_name), if extension == "sv" { "svh" } else { "vh" });
-    fs::write(target_path.join(&header_filename), header_content)?;
-    println!("Generating header file: {}", target_path.join(&header_filename).to_str().unwrap());
-
-    let full_module_path = target_path.join(&module_name_with_ext);
-    update_lockfile(&full_module_path, url, &contents, visited, is_top_module)?;
-
+/// Records that `upstream_path` is copied to `full_path`. Fails if another
+/// file already goes there: one copied earlier in this walk, or one that
+/// vpm.lock records from a different repository or path.
+fn claim_destination(repo: &Checkout, upstream_path: &Path, full_path: &str, walk: &mut Walk) -> Result<()> {
+    let path = lock_path(upstream_path);
+    if let Some(other) = walk.copied.iter().find(|(_, copy)| *copy == full_path).map(|(other, _)| other) {
+        anyhow::bail!("'{}' and '{}' of {} would both be copied to '{}'", lock_path(other), path, repo.url, full_path);
+    }
+    if let Some(entry) = walk.lockfile.get(full_path).filter(|e| e.source != repo.url || e.path != path) {
+        let holder = if entry.source.is_empty() { "a file of the project".to_string() } else { format!("'{}' of {}", entry.path, entry.source) };
+        anyhow::bail!(
+            "'{}' already holds {}; not overwriting it with '{}' of {}. Include one of them with --namespace[=PREFIX] to copy it under another name.",
+            full_path, holder, path, repo.url
+        );
+    }
+    walk.copied.insert(upstream_path.to_path_buf(), full_path.to_string());
     Ok(())
 }
 
-fn download_and_process_submodules(package_name: &str, module_path: &str, destination: &str, url: &str, visited: &mut HashSet<String>, _is_top_module: bool, commit_hash: Option<&str>) -> Result<HashSet<String>> {
-    let module_name = Path::new(module_path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or(module_path);
-    // println!("Processing submodule: {}", module_path);
-    let module_name_with_ext = if module_path.ends_with(".sv") {
-        format!("{}.sv", module_name)
-    } else if module_path.ends_with(".v") {
-        format!("{}.v", module_name)
-    } else {
-        module_path.to_string()
-    };
-
-    let full_module_path = PathBuf::from(destination).join(&module_name_with_ext);
-    // println!("Full module path: {}", full_module_path.display());
-    let contents = match fs::read_to_string(&full_module_path) {
-        Ok(c) => c,
-        Err(e) => {
-            println!("Warning: Failed to read file {}: {}. Skipping this module.", full_module_path.display(), e);
-            return Ok(HashSet::new());
-        }
-    };
-
-    let submodules = match get_submodules(&contents) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Warning: Failed to get submodules from {}: {}. Continuing without submodules.", full_module_path.display(), e);
-            HashSet::new()
+/// Copies a non-HDL file verbatim and records it in vpm.lock.
+fn process_asset(repo: &Checkout, upstream_path: &Path, local_path: &Path, walk: &mut Walk, parent: Option<&str>) -> Result<()> {
+    if let Some(full_path) = walk.copied.get(upstream_path) {
+        if let Some(parent) = parent {
+            walk.lockfile.add_parent(full_path, parent);
         }
+        return Ok(());
+    }
+    let full_path = lock_path(local_path);
+    claim_destination(repo, upstream_path, &full_path, walk)?;
+
+    let contents = fs::read(repo.root.join(upstream_path))
+        .with_context(|| format!("Failed to read '{}' from {}", upstream_path.display(), repo.url))?;
+    fs::create_dir_all(local_path.parent().unwrap_or(Path::new(".")))?;
+    fs::write(local_path, &contents)?;
+    println!("Including asset '{}'", lock_path(upstream_path));
+
+    let lock_entry = LockEntry {
+        full_path,
+        source: repo.url.clone(),
+        kind: EntryKind::Asset,
+        commit: repo.commit.clone(),
+        path: lock_path(upstream_path),
+        checksum: checksum(&contents),
+        parents: parent.map(|p| vec![p.to_string()]).unwrap_or_default(),
+        ..Default::default()
     };
+    walk.lockfile.upsert(lock_entry);
+    Ok(())
+}
 
-    let mut all_submodules = HashSet::new();
+/// Everything a source file depends on, by edge type, as recorded in vpm.lock.
+#[derive(Debug, Default)]
+pub struct Dependencies {
+    pub submodules: Vec<String>,
+    pub includes: Vec<String>,
+    pub packages: Vec<String>,
+    pub interfaces: Vec<String>,
+    pub assets: Vec<String>,
+}
 
-    for submodule in submodules {
-        let submodule_with_ext = if submodule.ends_with(".v") || submodule.ends_with(".sv") {
-            submodule.to_string()
-        } else {
-            let parent_extension = Path::new(&module_name_with_ext)
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("v");
-            format!("{}.{}", &submodule, parent_extension)
-        };
-        if !visited.contains(&submodule_with_ext) {
-            let submodule_destination = PathBuf::from(destination);
-            if let Err(e) = fs::create_dir_all(&submodule_destination) {
-                eprintln!("Warning: Failed to create directory {}: {}. Skipping this submodule.", submodule_destination.display(), e);
-                continue;
-            }
-            
-            match process_module(
-                package_name,
-                &submodule_with_ext,
-                submodule_destination.to_str().unwrap().to_string(),
-                visited,
-                &url,
-                false,
-                commit_hash.clone()
-            ) {
-                Ok(processed_submodules) => {
-                    all_submodules.insert(submodule_with_ext.clone());
-                    all_submodules.extend(processed_submodules);
-                },
+impl Dependencies {
+    /// Finds the dependencies of `contents` that are not declared in the file itself,
+    /// resolving packages and interfaces against `index`.
+    pub fn scan(index: &ModuleIndex, contents: &str) -> Self {
+        let defined = defined_names(contents);
+        let references = references(contents);
+        let external = |name: &String| !defined.contains(name);
+
+        let mut submodules: Vec<String> = if declares_module(contents) {
+            match get_submodules(contents) {
+                Ok(s) => s.into_iter()
+                    .filter(external)
+                    .filter(|s| !index.contains(s, DeclKind::Interface))
+                    .collect(),
                 Err(e) => {
-                    eprintln!("Warning: Failed to process submodule {}: {}. Skipping this submodule.", submodule_with_ext, e);
-                    continue;
+                    eprintln!("Warning: Failed to get submodules: {}. Continuing without submodules.", e);
+                    Vec::new()
                 }
             }
-
-            let full_submodule_path = submodule_destination.join(&submodule_with_ext);
-            if let Err(e) = update_lockfile(&full_submodule_path, &url, &contents, visited, false) {
-                eprintln!("Warning: Failed to update lockfile for {}: {}. Continuing without updating lockfile.", full_submodule_path.display(), e);
-            }
+        } else {
+            Vec::new()
+        };
+        submodules.sort();
+        Dependencies {
+            submodules,
+            includes: references.includes,
+            assets: references.data_files,
+            packages: references.packages.into_iter()
+                .filter(external)
+                .filter(|p| index.contains(p, DeclKind::Package))
+                .collect(),
+            interfaces: references.identifiers.into_iter()
+                .filter(external)
+                .filter(|i| index.contains(i, DeclKind::Interface))
+                .collect(),
         }
     }
-
-    Ok(all_submodules)
 }
 
-fn update_lockfile(full_path: &PathBuf, url: &str, contents: &str, visited: &HashSet<String>, is_top_module: bool) -> Result<()> {
-    let mut lockfile = fs::read_to_string("vpm.lock").unwrap_or_default();
-    let module_entry = if is_top_module {
-        format!("[[package]]\nfull_path = \"{}\"\nsource = \"{}\"\nparents = []\n", full_path.display(), url)
-    } else {
-        format!("[[package]]\nfull_path = \"{}\"\nsource = \"{}\"\n", full_path.display(), url)
+/// Finds the file declaring `name`, asking the user to choose when several
+/// files declare it.
+fn resolve_declaration(repo: &Checkout, kind: DeclKind, name: &str, walk: &mut Walk) -> Result<Option<PathBuf>> {
+    if let Some(resolved) = walk.resolved.get(&(kind, name.to_string())) {
+        return Ok(resolved.clone());
+    }
+    let declarations = repo.index().lookup(name, kind);
+    let resolved = match declarations.as_slice() {
+        [] => None,
+        [declaration] => Some(declaration.path.clone()),
+        _ if !io::stdin().is_terminal() => {
+            // Declarations are ordered by path, so the choice is the same on every run.
+            let declaration = declarations[0];
+            println!(
+                "Multiple {}s found for '{}': {}. Input is not a terminal, so using {}:{}.",
+                kind, name,
+                declarations.iter().map(|d| format!("{}:{}", d.path.display(), d.line)).collect::<Vec<_>>().join(", "),
+                declaration.path.display(), declaration.line
+            );
+            Some(declaration.path.clone())
+        }
+        _ => {
+            println!("Multiple {}s found for '{}'. Please choose:", kind, name);
+            for (i, declaration) in declarations.iter().enumerate() {
+                println!("{}: {}:{}", i + 1, declaration.path.display(), declaration.line);
+            }
+            let mut choice = String::new();
+            io::stdin().read_line(&mut choice)?;
+            let declaration = choice.trim().parse::<usize>().ok()
+                .and_then(|index| index.checked_sub(1))
+                .and_then(|index| declarations.get(index))
+                .ok_or_else(|| anyhow::anyhow!("Invalid choice '{}': expected a number from 1 to {}", choice.trim(), declarations.len()))?;
+            Some(declaration.path.clone())
+        }
     };
+    walk.resolved.insert((kind, name.to_string()), resolved.clone());
+    Ok(resolved)
+}
 
-    let submodules = get_submodules(contents)?;
-    let submodules_vec: Vec<String> = submodules.into_iter().collect();
-
-    if !lockfile.contains(&format!("full_path = \"{}\"", full_path.display())) {
-        let formatted_submodules = submodules_vec.iter()
-            .map(|s| format!("  \"{}\",", s))
-            .collect::<Vec<_>>()
-            .join("\n");
-        lockfile.push_str(&format!("\n{}\nsubmodules = [\n{}\n]\n", module_entry, formatted_submodules));
-    } else {
-        update_submodules(&mut lockfile, &module_entry, &submodules_vec);
-    }
-
-    for submodule in &submodules_vec {
-        if !visited.contains(submodule) {
-            let submodule_path = full_path.parent().unwrap().join(submodule);
-            if let Some(existing_entry) = lockfile.find(&format!("\n[[package]]\nfull_path = \"{}\"", submodule_path.display())) {
-                let parent_start = lockfile[existing_entry..].find("parents = [").map(|i| existing_entry + i);
-                if let Some(start) = parent_start {
-                    let end = lockfile[start..].find(']').map(|i| start + i + 1).unwrap_or(lockfile.len());
-                    let current_parents = lockfile[start..end].to_string();
-                    let new_parents = if current_parents.contains(&full_path.display().to_string()) {
-                        current_parents
-                    } else {
-                        format!("{}  \"{}\",\n]", &current_parents[..current_parents.len() - 1], full_path.display())
-                    };
-                    lockfile.replace_range(start..end, &new_parents);
+/// Resolves a file named in `from` (an `` `include `` or a memory image) the
+/// way a simulator would: next to `from`, then from the repository root, then
+/// from any directory of the repository (the include directories are not
+/// known), preferring the shortest path.
+fn resolve_file(repo: &Checkout, from: &Path, include: &str) -> Option<PathBuf> {
+    let root = repo.root.canonicalize().ok()?;
+    let relative = from.parent().unwrap_or(Path::new("")).join(include);
+    for candidate in [relative.as_path(), Path::new(include)] {
+        if let Ok(path) = root.join(candidate).canonicalize() {
+            if path.is_file() {
+                if let Ok(path) = path.strip_prefix(&root) {
+                    return Some(path.to_path_buf());
                 }
-            } else {
-                let submodule_entry = format!("\n[[package]]\nfull_path = \"{}\"\nsource = \"{}\"\nparents = [\n  \"{}\",\n]\nsubmodules = []\n", submodule_path.display(), url, full_path.display());
-                lockfile.push_str(&submodule_entry);
             }
         }
     }
+    WalkDir::new(&root)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != ".git")
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file() && e.path().ends_with(include))
+        .filter_map(|e| e.path().strip_prefix(&root).ok().map(Path::to_path_buf))
+        .min_by_key(|p| (p.components().count(), p.clone()))
+}
+
+/// New names for the modules `contents` declares or instantiates from `repo`:
+/// each gets `prefix`, unless it already starts with it.
+fn namespace(repo: &Checkout, contents: &str, prefix: &str) -> BTreeMap<String, String> {
+    let index = repo.index();
+    let instantiated = if declares_module(contents) { get_submodules(contents).unwrap_or_default() } else { HashSet::new() };
+    declarations(contents).into_iter()
+        .filter(|(kind, _, _)| *kind == DeclKind::Module)
+        .map(|(_, name, _)| name)
+        .chain(instantiated.into_iter().filter(|name| index.contains(name, DeclKind::Module)))
+        .filter(|name| !name.starts_with(&format!("{}_", prefix)))
+        .map(|name| (name.clone(), format!("{}_{}", prefix, name)))
+        .collect()
+}
+
+/// Warns about names that more than one file of the project declares, which
+/// simulators and synthesis tools reject.
+fn report_duplicates() -> Result<()> {
+    let lockfile = Lockfile::load("vpm.lock")?;
+    let duplicates = lockfile.duplicate_names();
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    println!("Warning: {} name(s) are declared by more than one file of the project:", duplicates.len());
+    for (name, entries) in &duplicates {
+        let files: Vec<String> = entries.iter()
+            .map(|e| if e.source.is_empty() { e.full_path.clone() } else { format!("{} (from {})", e.full_path, e.source) })
+            .collect();
+        println!("  {}: {}", name, files.join(", "));
+    }
+    println!("Remove one of the dependencies and include it again with --namespace[=PREFIX] to prefix the names of its modules.");
+    Ok(())
+}
+
+/// Where a file being included comes from, as recorded in vpm.lock.
+struct LockSource<'a> {
+    repo: &'a Checkout,
+    parent: Option<&'a str>,
+    /// Modules renamed in the file, by upstream name.
+    renames: &'a BTreeMap<String, String>,
+}
+
+/// Writes `contents` of `upstream_path` to `local_path`, generates its header
+/// and records it in vpm.lock.
+fn process_file(upstream_path: &Path, local_path: &Path, contents: &str, dependencies: &Dependencies, source: &LockSource, lockfile: &mut Lockfile) -> Result<()> {
+    let target_path = local_path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(target_path)?;
+    fs::write(local_path, contents)?;
+
+    let module_name = local_path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    write_header(target_path, module_name, local_path, contents)?;
+
+    let lock_entry = LockEntry {
+        full_path: lock_path(local_path),
+        source: source.repo.url.clone(),
+        kind: EntryKind::Hdl,
+        commit: source.repo.commit.clone(),
+        revision: String::new(),
+        path: lock_path(upstream_path),
+        checksum: checksum(contents.as_bytes()),
+        parents: source.parent.map(|p| vec![p.to_string()]).unwrap_or_default(),
+        defines: defined_names(contents),
+        submodules: dependencies.submodules.iter()
+            .map(|name| source.renames.get(name).unwrap_or(name).clone())
+            .collect(),
+        includes: dependencies.includes.clone(),
+        packages: dependencies.packages.clone(),
+        interfaces: dependencies.interfaces.clone(),
+        assets: dependencies.assets.clone(),
+        renames: source.renames.clone(),
+    };
+    lockfile.upsert(lock_entry);
 
-    fs::write("vpm.lock", lockfile)?;
     Ok(())
 }
 
-fn update_submodules(lockfile: &mut String, module_entry: &str, submodules: &[String]) {
-    if let Some(start) = lockfile.find(module_entry).and_then(|pos| lockfile[pos..].find("submodules = [").map(|offset| pos + offset)) {
-        let end = lockfile[start..].find(']').map(|pos| start + pos + 1).unwrap_or(lockfile.len());
-        let new_modules = format!("submodules = [\n{}\n]", submodules.iter().map(|m| format!("  \"{}\",", m)).collect::<Vec<_>>().join("\n"));
-        lockfile.replace_range(start..end, &new_modules);
+/// Generates the `.vh`/`.svh` header for `module_file` next to it. Files that
+/// declare no module (packages, `` `include `` files) get no header.
+pub fn write_header(target_path: &Path, module_name: &str, module_file: &Path, contents: &str) -> Result<()> {
+    if !declares_module(contents) {
+        return Ok(());
     }
+    let extension = module_file.extension().and_then(|s| s.to_str()).unwrap_or("v");
+    let header_content = generate_headers(contents)?;
+    let header_filename = format!("{}.{}", module_name.strip_suffix(".v").unwrap_or(module_name), if extension == "sv" { "svh" } else { "vh" });
+    fs::write(target_path.join(&header_filename), header_content)?;
+    println!("Generating header file: {}", target_path.join(&header_filename).to_str().unwrap());
+    Ok(())
+}
+
+/// Normalizes a project path for use as a lockfile key.
+pub fn lock_path(path: &Path) -> String {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    path.to_string_lossy().replace('\\', "/")
 }
 
-pub fn include_repo_from_url(url: &str, location: &str, commit_hash: Option<&str>, is_head: bool) -> Result<()> {
-    let repo_path = Path::new(location).join(name_from_url(url));
+/// Opens `commit_hash` of `url` from the repository cache, fetching it if needed.
+pub fn include_repo_from_url(url: &str, commit_hash: &str) -> Result<Checkout> {
     let pb = ProgressBar::new_spinner();
     pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}").unwrap());
     pb.set_message("Reading repository...");
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
-    clone_repo(url, &repo_path, commit_hash, is_head)?;
+    let checkout = cache::checkout(url, commit_hash)
+        .with_context(|| format!("Failed to clone repository from URL: '{}'", url))?;
     pb.finish_with_message("Reading repository complete");
+    Ok(checkout)
+}
+
+/// Copies a cached checkout into the project, leaving out its git metadata.
+fn copy_repo(repo_root: &Path, destination: &Path) -> Result<()> {
+    for entry in WalkDir::new(repo_root).into_iter().filter_entry(|e| e.file_name() != ".git") {
+        let entry = entry?;
+        let target = destination.join(entry.path().strip_prefix(repo_root)?);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    println!("Copied repository to {}", destination.display());
     Ok(())
 }
 
-pub fn clone_repo(url: &str, repo_path: &Path, commit_hash: Option<&str>, is_head: bool) -> Result<()> {
-    if repo_path.exists() {
-        fs::remove_dir_all(repo_path)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sources() {
+        assert_eq!(
+            parse_source("https://github.com/ZipCPU/zipcpu/blob/master/rtl/core/pfcache.v", None, false),
+            ("https://github.com/ZipCPU/zipcpu".to_string(), Some("rtl/core/pfcache.v".to_string()))
+        );
+        assert_eq!(
+            parse_source("https://gitlab.com/group/ip/-/blob/v1.0/src/fifo.sv", None, false),
+            ("https://gitlab.com/group/ip".to_string(), Some("src/fifo.sv".to_string()))
+        );
+        // An explicit --path wins over a file link.
+        assert_eq!(
+            parse_source("https://github.com/ZipCPU/zipcpu/", Some("/rtl/core/pfcache.v"), false),
+            ("https://github.com/ZipCPU/zipcpu".to_string(), Some("rtl/core/pfcache.v".to_string()))
+        );
+        assert_eq!(parse_source("ZipCPU/zipcpu", None, true), ("https://github.com/ZipCPU/zipcpu".to_string(), None));
+        assert_eq!(parse_source("ZipCPU/zipcpu", None, false), ("ZipCPU/zipcpu".to_string(), None));
+        assert_eq!(parse_source("git@github.com:ZipCPU/zipcpu.git", None, true), ("git@github.com:ZipCPU/zipcpu.git".to_string(), None));
     }
-    Command::new("git")
-        .args([ "clone", "--depth", "1", "--single-branch", "--jobs", "4",
-            url, repo_path.to_str().unwrap_or_default(),
-        ])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .status()
-        .with_context(|| format!("Failed to clone repository from URL: '{}'", url))?;
 
-    println!("Cloned repository: {}", repo_path.to_str().unwrap_or_default());
-    if !is_head {
-        if let Some(hash) = commit_hash {
-            Command::new("git")
-            .args([ "-C", repo_path.to_str().unwrap_or_default(), "checkout", hash ])
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .status()
-            .with_context(|| format!("Failed to checkout commit hash: '{}'", hash))?;
-        }
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("*.sv", "rtl/core/fifo.sv"));
+        assert!(!glob_match("*.sv", "rtl/core/fifo.v"));
+        assert!(glob_match("rtl/*.v", "rtl/fifo.v"));
+        assert!(!glob_match("rtl/*.v", "rtl/core/fifo.v"));
+        assert!(glob_match("rtl/**/*.sv", "rtl/fifo.sv"));
+        assert!(glob_match("rtl/**/*.sv", "rtl/core/sub/fifo.sv"));
+        assert!(!glob_match("rtl/**/*.sv", "lib/rtl/fifo.sv"));
+        assert!(glob_match("fifo_?.v", "fifo_a.v"));
+        assert!(!glob_match("fifo_?.v", "fifo_ab.v"));
+        assert!(glob_match("**", "any/path/at/all.v"));
     }
-    Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use parsv::get_submodules;
+use walkdir::WalkDir;
+
+use crate::cmd::{Execute, Init};
+use crate::cmd::include::lock_path;
+use crate::index::{declarations, references, DeclKind, ModuleIndex};
+use crate::lockfile::{with_lockfile, EntryKind, LockEntry};
+use crate::project::MANIFEST;
+use crate::toml::{Package, VpmToml};
+
+const SCANNED_EXTENSIONS: [&str; 2] = ["v", "sv"];
+
+impl Execute for Init {
+    async fn execute(&self) -> Result<()> {
+        let dir = env::current_dir()?;
+        anyhow::ensure!(!dir.join(MANIFEST).exists(), "{} already exists in '{}'", MANIFEST, dir.display());
+
+        let defaults = Package {
+            name: dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+            ..Package::default()
+        };
+        let interactive = !self.yes && io::stdin().is_terminal();
+        let ask = |question: &str, value: &Option<String>, default: &str| -> Result<String> {
+            match value {
+                Some(value) => Ok(value.clone()),
+                None if interactive => prompt(question, default),
+                None => Ok(default.to_string()),
+            }
+        };
+
+        let name = ask("Package name", &self.name, &defaults.name)?;
+        anyhow::ensure!(!name.trim().is_empty(), "The package name must not be empty");
+        let version = ask("Version", &self.version, &defaults.version)?;
+        semver::Version::parse(&version)
+            .map_err(|e| anyhow::anyhow!("Invalid version '{}': {}", version, e))?;
+        let authors = if !self.authors.is_empty() {
+            self.authors.clone()
+        } else if interactive {
+            let authors = prompt("Authors, separated by commas", &defaults.authors.join(", "))?;
+            authors.split(',').map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect()
+        } else {
+            defaults.authors
+        };
+        let description = ask("Description", &self.description, &defaults.description)?;
+        let license = ask("License", &self.license, &defaults.license)?;
+
+        let scan = if self.scan { Some(scan(&dir)) } else { None };
+
+        let package = Package { name, version, authors, description, license };
+        VpmToml::create(MANIFEST, &package)?;
+        println!("Created {} for package '{}' in '{}'", MANIFEST, package.name, dir.display());
+
+        if let Some(scan) = scan {
+            let files = scan.entries.len();
+            with_lockfile(|lockfile| {
+                for entry in scan.entries {
+                    lockfile.upsert(entry);
+                }
+                Ok(())
+            })?;
+            println!("Recorded {} local file(s) in vpm.lock", files);
+            print_scan(&scan.top_modules, &scan.unresolved);
+        }
+        Ok(())
+    }
+}
+
+/// What `vpm init --scan` found in the project's own sources.
+struct Scan {
+    entries: Vec<LockEntry>,
+    /// Modules no scanned file instantiates, with the file declaring them.
+    top_modules: Vec<(String, String)>,
+    /// Instantiated modules that nothing in the project declares, with the
+    /// files instantiating them.
+    unresolved: BTreeMap<String, Vec<String>>,
+}
+
+/// Parses every `.v` and `.sv` file below `root`, outside of hidden
+/// directories and vpm_modules/, into local lockfile entries linked by the
+/// modules, packages and interfaces they use.
+fn scan(root: &Path) -> Scan {
+    let index = ModuleIndex::build(root);
+    let mut files: Vec<_> = WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !(e.file_name().to_string_lossy().starts_with('.') || e.file_name() == "vpm_modules"))
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.path().extension().and_then(|ext| ext.to_str()).is_some_and(|ext| SCANNED_EXTENSIONS.contains(&ext)))
+        .map(|e| e.path().strip_prefix(root).unwrap_or(e.path()).to_path_buf())
+        .collect();
+    files.sort();
+
+    let mut entries = Vec::new();
+    let mut modules = Vec::new();
+    // Every instantiated name, including modules instantiated in their own file.
+    let mut instantiated = BTreeSet::new();
+    for path in files {
+        let Ok(contents) = fs::read_to_string(root.join(&path)) else {
+            eprintln!("Warning: Failed to read {}. Skipping it.", path.display());
+            continue;
+        };
+        let declared = declarations(&contents);
+        let defines: Vec<String> = declared.iter().map(|(_, name, _)| name.clone()).collect();
+        let own = |name: &String| !defines.contains(name);
+        let mut submodules: Vec<String> = if declared.iter().any(|(kind, _, _)| *kind == DeclKind::Module) {
+            match get_submodules(&contents) {
+                Ok(s) => s.into_iter()
+                    .inspect(|s| { instantiated.insert(s.clone()); })
+                    .filter(own)
+                    .filter(|s| !index.contains(s, DeclKind::Interface))
+                    .collect(),
+                Err(e) => {
+                    eprintln!("Warning: Failed to parse {}: {}. Its submodules are not recorded.", path.display(), e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        submodules.sort();
+        let references = references(&contents);
+        modules.push(declared.iter()
+            .filter(|(kind, _, _)| *kind == DeclKind::Module)
+            .map(|(_, name, _)| name.clone())
+            .collect::<Vec<_>>());
+        entries.push(LockEntry {
+            full_path: lock_path(&path),
+            kind: EntryKind::Local,
+            submodules,
+            includes: references.includes,
+            packages: references.packages.into_iter()
+                .filter(own)
+                .filter(|p| index.contains(p, DeclKind::Package))
+                .collect(),
+            interfaces: references.identifiers.into_iter()
+                .filter(own)
+                .filter(|i| index.contains(i, DeclKind::Interface))
+                .collect(),
+            assets: references.data_files,
+            defines,
+            ..Default::default()
+        });
+    }
+
+    // Link every file to the files declaring what it uses.
+    let mut declaring: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        for name in &entry.defines {
+            declaring.entry(name).or_default().push(i);
+        }
+    }
+    let mut parents: Vec<Vec<String>> = vec![Vec::new(); entries.len()];
+    let mut unresolved: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for entry in &entries {
+        for name in entry.submodules.iter().chain(&entry.packages).chain(&entry.interfaces) {
+            match declaring.get(name.as_str()) {
+                Some(declared_in) => declared_in.iter().for_each(|&i| parents[i].push(entry.full_path.clone())),
+                // Declared in a header or in vpm_modules/: resolved, but not a scanned file.
+                None if [DeclKind::Module, DeclKind::Package, DeclKind::Interface].into_iter().any(|kind| index.contains(name, kind)) => {}
+                None => unresolved.entry(name.clone()).or_default().push(entry.full_path.clone()),
+            }
+        }
+    }
+
+    let top_modules = modules.iter().zip(&entries)
+        .flat_map(|(names, entry)| names.iter()
+            .filter(|name| !instantiated.contains(*name))
+            .map(|name| (name.clone(), entry.full_path.clone())))
+        .collect();
+    for (entry, mut parents) in entries.iter_mut().zip(parents) {
+        parents.dedup();
+        entry.parents = parents;
+    }
+    Scan { entries, top_modules, unresolved }
+}
+
+fn print_scan(top_modules: &[(String, String)], unresolved: &BTreeMap<String, Vec<String>>) {
+    if top_modules.is_empty() {
+        println!("No top modules found: every module is instantiated by another one.");
+    } else {
+        println!("Top modules:");
+        for (name, path) in top_modules {
+            println!("  {} ({})", name, path);
+        }
+    }
+    if unresolved.is_empty() {
+        println!("Every instantiated module is declared in the project.");
+    } else {
+        println!("Modules instantiated but not declared in the project. Add them with `vpm include`:");
+        for (name, files) in unresolved {
+            println!("  {} (instantiated in {})", name, files.join(", "));
+        }
+    }
+}
+
+/// Asks `question` on the terminal; an empty answer takes `default`.
+fn prompt(question: &str, default: &str) -> Result<String> {
+    print!("{} ({}): ", question, default);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
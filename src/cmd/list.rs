@@ -1,13 +1,15 @@
 use anyhow::{Result, Context, anyhow};
 use std::collections::HashSet;
-use std::process::Command;
 use crate::cmd::{Execute, List};
+use crate::git;
+use crate::network;
 use tempfile::tempdir;
 
 const STD_LIB_URL: &str = "https://github.com/getinstachip/openchips";
 
 impl Execute for List {
     async fn execute(&self) -> Result<()> {
+        network::ensure_online("vpm list")?;
         match list_verilog_files() {
             Ok(verilog_files) => {
                 println!("Available Verilog modules:");
@@ -33,7 +35,7 @@ fn list_verilog_files() -> Result<Vec<String>> {
     let repo_path = temp_dir.path();
 
     // Clone the repository
-    let output = Command::new("git")
+    let output = git::git_remote(STD_LIB_URL)
         .args([
             "clone",
             "--depth",
@@ -41,7 +43,7 @@ fn list_verilog_files() -> Result<Vec<String>> {
             "--single-branch",
             "--jobs",
             "4",
-            STD_LIB_URL,
+            &git::remote(STD_LIB_URL),
             repo_path.to_str().unwrap_or_default(),
         ])
         .output()
@@ -1,6 +1,12 @@
+#[allow(clippy::module_inception)]
 mod cmd;
+mod init;
 mod upgrade;
 mod include;
+mod show;
+mod fetch;
+mod cache;
+mod vendor;
 mod update;
 mod remove;
 mod list;
@@ -13,20 +19,72 @@ mod load;
 mod config;
 mod test;
 mod restructure;
+mod wrapper;
 
 use anyhow::Result;
 
 pub use crate::cmd::cmd::*;
 use crate::config_man::send_event;
+use crate::project::{exists_from_invocation, from_invocation};
 
 pub trait Execute {
     async fn execute(&self) -> Result<()>;
 }
 
+impl Cmd {
+    /// Rewrites the path arguments, which are given relative to the directory
+    /// vpm was started in, to resolve from the project root.
+    pub fn rebase_paths(&mut self) {
+        let rebase = |path: &mut String| *path = from_invocation(path);
+        let is_local = |source: &str| !source.contains("://") && exists_from_invocation(source);
+        match self {
+            // Sources are only paths when they name a local directory or archive.
+            Cmd::Include(cmd) => {
+                if is_local(&cmd.url) {
+                    rebase(&mut cmd.url);
+                }
+            },
+            Cmd::Show(cmd) => {
+                if is_local(&cmd.source) {
+                    rebase(&mut cmd.source);
+                }
+            },
+            Cmd::Vendor(cmd) => match &mut cmd.action {
+                VendorAction::Export { output } => rebase(output),
+                VendorAction::Import { bundle, .. } => bundle.iter_mut().for_each(rebase),
+            },
+            Cmd::Update(cmd) => rebase(&mut cmd.module_path),
+            Cmd::Remove(cmd) => rebase(&mut cmd.package_path),
+            Cmd::Docs(cmd) => {
+                if !cmd.from_repo || is_local(&cmd.module_path) {
+                    rebase(&mut cmd.module_path);
+                }
+            },
+            Cmd::Sim(cmd) => cmd.verilog_files.iter_mut().for_each(rebase),
+            Cmd::Synth(cmd) => {
+                rebase(&mut cmd.top_module_path);
+                cmd.core_path.iter_mut().for_each(rebase);
+            },
+            Cmd::Load(cmd) => {
+                rebase(&mut cmd.top_module_path);
+                rebase(&mut cmd.constraints_path);
+            },
+            Cmd::Run(cmd) => rebase(&mut cmd.program_path),
+            Cmd::Test(cmd) => rebase(&mut cmd.module_path),
+            Cmd::Restructure(cmd) => rebase(&mut cmd.top_module_path),
+            Cmd::Init(_) | Cmd::Upgrade(_) | Cmd::Fetch(_) | Cmd::Cache(_) | Cmd::Install(_) | Cmd::List(_) | Cmd::Config(_) => {},
+        }
+    }
+}
 
 impl Execute for Cmd {
     async fn execute(&self) -> Result<()> {
         match self {
+            Cmd::Init(cmd) => {
+                cmd.execute().await?;
+                send_event("init".to_string()).await?;
+                Ok(())
+            },
             Cmd::Upgrade(cmd) => {
                 cmd.execute().await?;
                 send_event("upgrade".to_string()).await?;
@@ -37,6 +95,29 @@ impl Execute for Cmd {
                 send_event("include".to_string()).await?;
                 Ok(())
             },
+            Cmd::Show(cmd) => {
+                cmd.execute().await?;
+                send_event("show".to_string()).await?;
+                Ok(())
+            },
+            Cmd::Fetch(cmd) => {
+                cmd.execute().await?;
+                send_event("fetch".to_string()).await?;
+                Ok(())
+            },
+            Cmd::Cache(cmd) => {
+                cmd.execute().await?;
+                send_event("cache".to_string()).await?;
+                Ok(())
+            },
+            Cmd::Vendor(cmd) => {
+                cmd.execute().await?;
+                // Bundles are imported on machines without network access.
+                if matches!(cmd.action, VendorAction::Export { .. }) {
+                    send_event("vendor".to_string()).await?;
+                }
+                Ok(())
+            },
             Cmd::Update(cmd) => {
                 cmd.execute().await?;
                 send_event("update".to_string()).await?;
@@ -5,6 +5,8 @@ use std::io::{self, Write};
 use anyhow::{anyhow, Context, Result};
 
 use crate::cmd::{Execute, Remove};
+use crate::cmd::include::lock_path;
+use crate::lockfile::with_lockfile;
 use crate::toml::{remove_top_module, get_repo_links};
 
 impl Execute for Remove {
@@ -31,7 +33,7 @@ fn remove_module(module_path: &str) -> Result<()> {
         return Ok(());
     }
 
-    let repo_links = get_repo_links(module_name);
+    let repo_links = get_repo_links(module_name)?;
 
     let repo_link = match repo_links.len() {
         0 => return Err(anyhow!("No repository links found for module: {}", module_name)),
@@ -76,5 +78,16 @@ fn remove_module(module_path: &str) -> Result<()> {
     remove_top_module(&repo_link, module_name)?;    
     println!("Removed module: {}", module_path.display());
 
+    // Submodules that were only pulled in by this module go with it
+    let lock_key = lock_path(&module_path);
+    let removed = with_lockfile(|lockfile| Ok(lockfile.remove(&lock_key)))?;
+    for entry in removed.iter().filter(|e| e.full_path != lock_key) {
+        let path = PathBuf::from(&entry.full_path);
+        if path.exists() {
+            fs::remove_file(&path)?;
+            println!("Removed submodule: {}", path.display());
+        }
+    }
+
     Ok(())
 }
@@ -2,6 +2,11 @@ use crate::cmd::{Execute, Restructure};
 use anyhow::{Result, Context};
 use parsv::get_submodules;
 use crate::toml;
+use crate::cmd::include::lock_path;
+use crate::archive;
+use crate::git::{self, Revision};
+use crate::index::defined_names;
+use crate::lockfile::{checksum, with_lockfile, EntryKind, LockEntry};
 // use indicatif::{ProgressBar, ProgressStyle};
 use std::collections::HashSet;
 use std::fs;
@@ -13,6 +18,7 @@ impl Execute for Restructure {
         if !Path::new(top_module_path).exists() {
             return Err(anyhow::anyhow!("Top module file does not exist. Ensure the path is correct."));
         }
+        toml::VpmToml::load("vpm.toml")?;
 
         // let pb = ProgressBar::new_spinner();
         // pb.set_style(ProgressStyle::default_spinner());
@@ -66,9 +72,11 @@ impl Execute for Restructure {
         let new_dir_name = format!("vpm_modules/{}/rtl", top_module_name);
         let new_dir = Path::new(&new_dir_name);
         fs::create_dir_all(new_dir)?;
+        let mut moved_files = Vec::new();
         for path in path_set {
             let new_path = new_dir.join(path.file_name().unwrap());
-            let result = fs::write(&new_path, fs::read(&path)?);
+            let contents = fs::read(&path)?;
+            let result = fs::write(&new_path, &contents);
             if let Err(e) = result {
                 println!("Failed to write to new file {}: {}. Skipping...", new_path.display(), e);
                 continue;
@@ -76,10 +84,11 @@ impl Execute for Restructure {
             if response == "move" {
                 fs::remove_file(&path)?;
             }
+            moved_files.push((path, new_path, contents));
         }
 
-        let version = loop {
-            print!("Please enter a version number for this module: ");
+        let origin = loop {
+            print!("Please enter the origin (e.g., GitHub URL) for this module: ");
             std::io::Write::flush(&mut std::io::stdout())?;
             let mut input = String::new();
             std::io::stdin().read_line(&mut input)?;
@@ -87,25 +96,46 @@ impl Execute for Restructure {
             if !input.is_empty() {
                 break input.to_string();
             }
-            println!("Invalid input. Please enter a non-empty version number.");
+            println!("Invalid input. Please enter a non-empty origin.");
         };
-        println!("Module version set to: {}", version);
+        println!("Module origin set to: {}", origin);
 
-        let origin = loop {
-            print!("Please enter the origin (e.g., GitHub URL) for this module: ");
+        // The files are the project's own copies, so they are recorded as local.
+        // Only a git origin can name the revision they correspond to.
+        let revision = if git::is_fetchable(&origin) && !archive::is_archive(&origin) {
+            print!("Please enter the tag, commit or version range of this module in '{}' (leave empty for none): ", origin);
             std::io::Write::flush(&mut std::io::stdout())?;
             let mut input = String::new();
             std::io::stdin().read_line(&mut input)?;
-            let input = input.trim();
-            if !input.is_empty() {
-                break input.to_string();
-            }
-            println!("Invalid input. Please enter a non-empty origin.");
+            Revision::from_version(input.trim())
+        } else {
+            Revision::Head
         };
-        println!("Module origin set to: {}", origin);
 
         toml::add_dependency(&origin).context("Failed to add dependency to toml file.")?;
-        toml::add_top_module(&origin,&format!("vpm_modules/{}/rtl/{}.{}", top_module_name, top_module_name, ext), &version).context("Failed to add top module to toml file.")?;
+        let top_full_path = format!("vpm_modules/{}/rtl/{}.{}", top_module_name, top_module_name, ext);
+        toml::add_top_module(&origin, &top_full_path, &revision).context("Failed to add top module to toml file.")?;
+
+        with_lockfile(|lockfile| {
+            for (old_path, new_path, contents) in &moved_files {
+                let full_path = lock_path(new_path);
+                let text = String::from_utf8_lossy(contents);
+                let mut submodules: Vec<String> = get_submodules(&text).unwrap_or_default().into_iter().collect();
+                submodules.sort();
+                lockfile.upsert(LockEntry {
+                    parents: if full_path == top_full_path { Vec::new() } else { vec![top_full_path.clone()] },
+                    full_path,
+                    source: origin.clone(),
+                    kind: EntryKind::Local,
+                    path: lock_path(old_path),
+                    checksum: checksum(contents),
+                    defines: defined_names(&text),
+                    submodules,
+                    ..Default::default()
+                });
+            }
+            Ok(())
+        }).context("Failed to update vpm.lock.")?;
 
         Ok(())
     }
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use parsv::generate_headers;
+use serde::Serialize;
+
+use crate::archive;
+use crate::cache::{self, Checkout};
+use crate::cmd::{Execute, Show};
+use crate::cmd::include::{draw_tree, include_repo_from_url, lock_path, parse_source, submodule_tree, Submodule};
+use crate::git::{self, resolve_revision, Revision};
+use crate::index::{defined_names, DeclKind};
+
+/// Everything `vpm show` reports about a module.
+#[derive(Debug, Serialize)]
+struct ModuleInfo {
+    module: String,
+    source: String,
+    /// Path of the module's file inside the source.
+    path: String,
+    /// Commit, or SHA-256 for archives, that was inspected.
+    revision: String,
+    /// The latest commit of a git source.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit: Option<CommitInfo>,
+    license: Option<String>,
+    /// Ports and parameters, as in the header generated on include.
+    header: Option<String>,
+    submodules: Vec<Submodule>,
+    /// Files and lines including the module would add to vpm_modules/.
+    files: usize,
+    lines: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct CommitInfo {
+    id: String,
+    author: String,
+    date: String,
+    summary: String,
+}
+
+impl Execute for Show {
+    async fn execute(&self) -> Result<()> {
+        // A link to a file only names the repository here; <MODULE> picks the module.
+        let (source, _) = parse_source(&self.source, None, true);
+        anyhow::ensure!(git::is_fetchable(&source), "'{}' is not a git URL, an archive or a local directory", source);
+        let revision = resolve_revision(&source, &Revision::Head)
+            .with_context(|| format!("Failed to resolve the latest revision of '{}'", source))?;
+        let repo = include_repo_from_url(&source, &revision)?;
+
+        let (module, path) = find_module(&repo, &self.module)?;
+        let contents = fs::read_to_string(repo.root.join(&path))
+            .with_context(|| format!("Failed to read '{}' of {}", path.display(), source))?;
+        let mut files = BTreeMap::from([(path.clone(), contents.lines().count())]);
+        let submodules = submodule_tree(&repo, &contents, &mut files);
+        let info = ModuleInfo {
+            module,
+            path: lock_path(&path),
+            revision: repo.commit.clone(),
+            commit: latest_commit(&repo),
+            license: license(&repo, &contents),
+            header: generate_headers(&contents).ok(),
+            submodules,
+            files: files.len(),
+            lines: files.values().sum(),
+            source,
+        };
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+        } else {
+            print_info(&info);
+        }
+        Ok(())
+    }
+}
+
+/// The name and file of `module`, which is either the name of a module
+/// declared in `repo` or the path of a file inside it.
+fn find_module(repo: &Checkout, module: &str) -> Result<(String, PathBuf)> {
+    let as_path = module.trim_start_matches('/');
+    if repo.root.join(as_path).is_file() {
+        let contents = fs::read_to_string(repo.root.join(as_path)).unwrap_or_default();
+        let name = defined_names(&contents).into_iter().next()
+            .unwrap_or_else(|| Path::new(as_path).file_stem().unwrap_or_default().to_string_lossy().to_string());
+        return Ok((name, PathBuf::from(as_path)));
+    }
+    match repo.index().lookup(module, DeclKind::Module).as_slice() {
+        [] => anyhow::bail!("No file in {} declares module '{}'", repo.url, module),
+        [declaration] => Ok((module.to_string(), declaration.path.clone())),
+        declarations => anyhow::bail!(
+            "Module '{}' is declared in several files of {}: {}. Pass the path of one of them instead.",
+            module, repo.url,
+            declarations.iter().map(|d| lock_path(&d.path)).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// The commit a git source was inspected at. Archives and plain directories have none.
+fn latest_commit(repo: &Checkout) -> Option<CommitInfo> {
+    if archive::is_archive(&repo.url) || git::is_plain_directory(&repo.url) {
+        return None;
+    }
+    let mirror = cache::mirror(&repo.url).ok()?;
+    let log = git::run_in(&mirror, &["log", "-1", "--format=%H%x00%an%x00%aI%x00%s", &repo.commit]).ok()?;
+    let mut fields = log.splitn(4, '\0').map(ToString::to_string);
+    Some(CommitInfo {
+        id: fields.next()?,
+        author: fields.next()?,
+        date: fields.next()?,
+        summary: fields.next().unwrap_or_default(),
+    })
+}
+
+/// Well-known license texts by a phrase they contain, most specific first.
+const LICENSES: [(&str, &str); 8] = [
+    ("Apache License", "Apache-2.0"),
+    ("Mozilla Public License", "MPL-2.0"),
+    ("CERN Open Hardware Licence", "CERN-OHL"),
+    ("Solderpad Hardware License", "SHL"),
+    ("ISC License", "ISC"),
+    ("Neither the name", "BSD-3-Clause"),
+    ("Redistribution and use in source and binary forms", "BSD-2-Clause"),
+    ("Permission is hereby granted, free of charge", "MIT"),
+];
+
+fn spdx_id(text: &str) -> Option<&'static str> {
+    let version_3 = text.contains("Version 3");
+    if text.contains("GNU LESSER GENERAL PUBLIC LICENSE") {
+        return Some(if version_3 { "LGPL-3.0" } else { "LGPL-2.1" });
+    }
+    if text.contains("GNU GENERAL PUBLIC LICENSE") {
+        return Some(if version_3 { "GPL-3.0" } else { "GPL-2.0" });
+    }
+    LICENSES.iter().find(|(phrase, _)| text.contains(phrase)).map(|(_, id)| *id)
+}
+
+/// The license of a module: the `SPDX-License-Identifier` in its file, else
+/// the license file at the root of its repository, named by its SPDX
+/// identifier when the text is recognized.
+fn license(repo: &Checkout, contents: &str) -> Option<String> {
+    if let Some((_, rest)) = contents.split_once("SPDX-License-Identifier:") {
+        let id = rest.lines().next().unwrap_or_default().trim().trim_end_matches("*/").trim();
+        if !id.is_empty() {
+            return Some(id.to_string());
+        }
+    }
+    let file = fs::read_dir(&repo.root).ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .find(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_uppercase();
+            ["LICENSE", "LICENCE", "COPYING"].iter().any(|prefix| name.starts_with(prefix))
+        })?;
+    let name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let text = fs::read_to_string(&file).unwrap_or_default();
+    Some(match spdx_id(&text) {
+        Some(id) => format!("{} ({})", id, name),
+        None => name,
+    })
+}
+
+fn print_info(info: &ModuleInfo) {
+    println!("{} ({})", info.module, info.path);
+    println!("Source:  {}", info.source);
+    match &info.commit {
+        Some(commit) => println!("Commit:  {} ({}, {}) {}", commit.id, commit.author, commit.date, commit.summary),
+        None if !info.revision.is_empty() => println!("SHA-256: {}", info.revision),
+        None => {}
+    }
+    println!("License: {}", info.license.as_deref().unwrap_or("not found"));
+    println!("Adds:    {} file(s), {} line(s) to vpm_modules/", info.files, info.lines);
+    println!();
+    match &info.header {
+        Some(header) => println!("Ports and parameters:\n{}", header.trim_end()),
+        None => println!("Ports and parameters: the module declaration could not be parsed"),
+    }
+    println!();
+    let mut tree = vec![info.module.clone()];
+    draw_tree(&info.submodules, "", &mut tree);
+    println!("Submodules:\n{}", tree.join("\n"));
+}
@@ -5,7 +5,6 @@ use std::path::Path;
 // use std::fs;
 // use fastrand;
 use crate::cmd::{Execute, Sim};
-use parsv;
 // use std::fs::File;
 // use std::io::{BufRead, BufReader};
 // use fancy_regex::Regex;
@@ -31,9 +30,9 @@ impl Execute for Sim {
                 .parent().unwrap()
                 .parent().unwrap()
                 .join("sim");
-            parsv::run_testbenches(file, &sim_dir.to_str().unwrap()).context("Failed to run testbenches. Ensure the path is correct and the files exist.")?;
+            parsv::run_testbenches(file, sim_dir.to_str().unwrap()).context("Failed to run testbenches. Ensure the path is correct and the files exist.")?;
             if self.waveform {
-                parsv::generate_waveform_images(&sim_dir.to_str().unwrap()).context("Failed to generate waveform images. Ensure the path is correct and the files exist.")?;
+                parsv::generate_waveform_images(sim_dir.to_str().unwrap()).context("Failed to generate waveform images. Ensure the path is correct and the files exist.")?;
             }
         }
 
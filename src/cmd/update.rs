@@ -5,8 +5,13 @@ use std::path::{Path, PathBuf};
 use anyhow::{Result, Context};
 
 use crate::cmd::{Execute, Update};
-use crate::cmd::include::{get_head_commit_hash, include_repo_from_url, name_from_url};
-use crate::toml::{get_repo_links, add_top_module, remove_top_module};
+use crate::cache::Checkout;
+use crate::cmd::include::{include_repo_from_url, lock_path, record_revision, Dependencies};
+use crate::git::{self, resolve_revision, Revision};
+use crate::index::{defined_names, rename_modules, DeclKind, ModuleIndex};
+use crate::lockfile::{checksum, with_lockfile, Lockfile};
+use crate::toml::{get_repo_links, add_top_module};
+use crate::cmd::wrapper;
 use imara_diff::intern::InternedInput;
 use imara_diff::{diff, Algorithm, UnifiedDiffBuilder};
 
@@ -15,12 +20,13 @@ impl Execute for Update {
         let module_path = &self.module_path;
         println!("Updating module '{}'", module_path);
         update_module(module_path, self.version.as_deref(), true).context("Failed to update module. Ensure the path is correct and the file exists.")?;
+        wrapper::refresh()?;
         Ok(())
     }
 }
 
 fn update_module(module_path: &str, commit: Option<&str>, is_top_module: bool) -> Result<()> {
-    let repo_links = get_repo_links(module_path);
+    let repo_links = get_repo_links(module_path)?;
     if repo_links.is_empty() {
         return Err(anyhow::anyhow!("No headers found for module '{}'", module_path));
     }
@@ -39,10 +45,11 @@ fn update_module(module_path: &str, commit: Option<&str>, is_top_module: bool) -
             .ok_or_else(|| anyhow::anyhow!("Invalid choice"))?
     };
 
-    let is_repo = chosen_repo.contains("github.com");
-    let commit_hash = if is_repo {
-        let head = get_head_commit_hash(&chosen_repo).unwrap();
-        commit.unwrap_or(&head).to_string()
+    let is_repo = git::is_fetchable(&chosen_repo);
+    let revision = commit.map(Revision::from_version).unwrap_or(Revision::Head);
+    let mut commit_hash = if is_repo {
+        resolve_revision(&chosen_repo, &revision)
+            .with_context(|| format!("Failed to resolve {} of '{}'", revision, chosen_repo))?
     } else {
         commit.map(ToString::to_string).unwrap_or_else(|| {
             print!("Please enter a version for the module: ");
@@ -56,20 +63,40 @@ fn update_module(module_path: &str, commit: Option<&str>, is_top_module: bool) -
     println!("Preparing to update module '{}' to commit '{}'", module_path, commit_hash);
     let old_contents = std::fs::read_to_string(module_path).context(format!("Failed to read module '{}'", module_path))?;
     
-    let new_contents = if is_repo {
-        let (_chosen_file, contents) = find_and_choose_module_file(&chosen_repo, module_path, &commit_hash)?;
-        contents
+    // Opened once and shared by the module and all of its submodules.
+    let repo = if is_repo {
+        let repo = include_repo_from_url(&chosen_repo, &commit_hash)?;
+        commit_hash = repo.commit.clone();
+        Some(repo)
+    } else {
+        None
+    };
+
+    let (upstream_path, new_contents) = if let Some(repo) = &repo {
+        let (chosen_file, contents) = find_and_choose_module_file(repo, module_path)?;
+        (Some(chosen_file), contents)
     } else {
         print!("Please enter the path to the new file: ");
         io::stdout().flush().unwrap();
         let mut new_file_path = String::new();
         io::stdin().read_line(&mut new_file_path)?;
-        std::fs::read_to_string(new_file_path.trim())?
+        (None, std::fs::read_to_string(new_file_path.trim())?)
     };
 
     let temp_path = format!("{}.temp", module_path);
     std::fs::write(&temp_path, &new_contents)?;
 
+    // Packages and interfaces are resolved against the repository the files came from,
+    // or against the project when they were picked by hand.
+    let project_index;
+    let index = match &repo {
+        Some(repo) => repo.index(),
+        None => {
+            project_index = ModuleIndex::build(Path::new("."));
+            &project_index
+        }
+    };
+
     let ext = Path::new(module_path).extension().unwrap_or_default().to_str().unwrap_or(".v");
 
     // Display the diff and ask for confirmation
@@ -80,9 +107,11 @@ fn update_module(module_path: &str, commit: Option<&str>, is_top_module: bool) -
     let mut choice = String::new();
     std::io::stdin().read_line(&mut choice)?;
 
-    if choice.trim().to_lowercase() == "y" {
+    let applied = choice.trim().to_lowercase() == "y";
+    if applied {
         // Apply the changes
         std::fs::rename(&temp_path, module_path)?;
+        record_update(Path::new(module_path), &commit_hash, upstream_path.as_deref(), &new_contents, index)?;
         println!("Module '{}' updated to commit '{}'", module_path, commit_hash);
     } else {
         // Remove the temporary file
@@ -99,23 +128,30 @@ fn update_module(module_path: &str, commit: Option<&str>, is_top_module: bool) -
     if submodule_choice.trim().to_lowercase() == "y" {
         // Get submodules from the updated module
         let submodules = parsv::get_submodules(&new_contents).context("Failed to get submodules")?;
+        let defined = defined_names(&new_contents);
+        let lockfile = Lockfile::load("vpm.lock")?;
+        let parent = lock_path(Path::new(module_path));
         
-        for submodule in submodules {
-            let submodule_path = Path::new(module_path).with_file_name(format!("{}.{}", submodule, ext));
+        for submodule in submodules.into_iter().filter(|s| !defined.contains(s)) {
+            // vpm.lock records which file each submodule was resolved to when it was included.
+            let submodule_path = lockfile.packages.iter()
+                .find(|e| e.parents.contains(&parent) && e.defines.contains(&submodule))
+                .map(|e| PathBuf::from(&e.full_path))
+                .unwrap_or_else(|| Path::new(module_path).with_file_name(format!("{}.{}", submodule, ext)));
             if submodule_path.exists() {
                 println!("Preparing to update submodule: {}", submodule);
                 let old_contents = fs::read_to_string(&submodule_path)?;
                 
                 // Use find_and_choose_module_file for submodules
-                let new_contents = if is_repo {
-                    let (_chosen_file, contents) = find_and_choose_module_file(&chosen_repo, &submodule_path.to_str().unwrap(), &commit_hash)?;
-                    contents
+                let (upstream_path, new_contents) = if let Some(repo) = &repo {
+                    let (chosen_file, contents) = find_and_choose_module_file(repo, submodule_path.to_str().unwrap())?;
+                    (Some(chosen_file), contents)
                 } else {
                     print!("Please enter the path to the new file: ");
                     io::stdout().flush().unwrap();
                     let mut new_file_path = String::new();
                     io::stdin().read_line(&mut new_file_path)?;
-                    std::fs::read_to_string(new_file_path.trim())?
+                    (None, std::fs::read_to_string(new_file_path.trim())?)
                 };
                 
                 // Create a temporary file for the new contents
@@ -133,6 +169,7 @@ fn update_module(module_path: &str, commit: Option<&str>, is_top_module: bool) -
                 if choice.trim().to_lowercase() == "y" {
                     // Apply the changes for the submodule
                     fs::rename(&temp_path, &submodule_path).context(format!("Failed to update submodule '{}'", submodule))?;
+                    record_update(&submodule_path, &commit_hash, upstream_path.as_deref(), &new_contents, index)?;
                     println!("Submodule '{}' updated to commit '{}'", submodule, commit_hash);
                 } else {
                     println!("Skipping submodule '{}'", submodule);
@@ -145,21 +182,44 @@ fn update_module(module_path: &str, commit: Option<&str>, is_top_module: bool) -
         }
     }
 
-    if is_top_module {
-        remove_top_module(&chosen_repo, &temp_path)?;
-        add_top_module(&chosen_repo, &temp_path, &commit_hash)?;
+    // vpm.toml only moves along with the file and its vpm.lock entry.
+    if is_top_module && applied {
+        let recorded = match &repo {
+            Some(repo) => repo.pinned(&revision),
+            None => Revision::Rev(commit_hash.clone()),
+        };
+        add_top_module(&chosen_repo, module_path, &recorded)?;
+        record_revision(Path::new(module_path), &recorded)?;
     }
 
     Ok(())
 }
 
-fn find_and_choose_module_file(chosen_repo: &str, module_path: &str, commit_hash: &str) -> Result<(PathBuf, String)> {
-    include_repo_from_url(chosen_repo, "/tmp/", Some(commit_hash), false)?;
-    
+fn find_and_choose_module_file(repo: &Checkout, module_path: &str) -> Result<(PathBuf, String)> {
+    let tmp_path = &repo.root;
     let module_name = Path::new(module_path).file_stem().unwrap().to_str().unwrap();
-    let tmp_path = PathBuf::from("/tmp").join(name_from_url(chosen_repo));
-    
-    let matching_files = find_matching_files(&tmp_path, module_name);
+
+    // Prefer the upstream file recorded in vpm.lock; fall back to the module index,
+    // and to file names for files that declare no module of that name.
+    let lockfile = Lockfile::load("vpm.lock")?;
+    let entry = lockfile.get(&lock_path(Path::new(module_path)));
+    let recorded = entry
+        .filter(|e| !e.path.is_empty())
+        .map(|e| tmp_path.join(&e.path))
+        .filter(|p| p.is_file());
+    let matching_files = match recorded {
+        Some(path) => vec![path],
+        None => {
+            let mut files: Vec<PathBuf> = repo.index().lookup(module_name, DeclKind::Module).into_iter()
+                .map(|d| tmp_path.join(&d.path))
+                .collect();
+            files.dedup();
+            if files.is_empty() {
+                files = find_matching_files(tmp_path, module_name);
+            }
+            files
+        }
+    };
     
     let chosen_file = if matching_files.is_empty() {
         return Err(anyhow::anyhow!("No matching files found for module '{}'", module_name));
@@ -168,7 +228,7 @@ fn find_and_choose_module_file(chosen_repo: &str, module_path: &str, commit_hash
     } else {
         println!("Multiple matches found for module '{}'. Please choose one:", module_name);
         for (index, file) in matching_files.iter().enumerate() {
-            println!("{}. {}", index + 1, file.strip_prefix(&tmp_path).unwrap().display());
+            println!("{}. {}", index + 1, file.strip_prefix(tmp_path).unwrap().display());
         }
         let mut choice = String::new();
         std::io::stdin().read_line(&mut choice)?;
@@ -178,10 +238,34 @@ fn find_and_choose_module_file(chosen_repo: &str, module_path: &str, commit_hash
             .clone()
     };
 
+    // A file included with --namespace keeps the names it was given.
     let new_contents = std::fs::read_to_string(&chosen_file)?;
+    let new_contents = entry.map_or(new_contents.clone(), |e| rename_modules(&new_contents, &e.renames));
+    let chosen_file = chosen_file.strip_prefix(tmp_path).unwrap_or(&chosen_file).to_path_buf();
     Ok((chosen_file, new_contents))
 }
 
+/// Points the vpm.lock entry of an updated file at its new upstream revision.
+fn record_update(module_path: &Path, commit_hash: &str, upstream_path: Option<&Path>, contents: &str, index: &ModuleIndex) -> Result<()> {
+    let dependencies = Dependencies::scan(index, contents);
+    with_lockfile(|lockfile| {
+        if let Some(entry) = lockfile.get_mut(&lock_path(module_path)) {
+            entry.commit = commit_hash.to_string();
+            entry.checksum = checksum(contents.as_bytes());
+            if let Some(upstream_path) = upstream_path {
+                entry.path = lock_path(upstream_path);
+            }
+            entry.defines = defined_names(contents);
+            entry.submodules = dependencies.submodules;
+            entry.includes = dependencies.includes;
+            entry.packages = dependencies.packages;
+            entry.interfaces = dependencies.interfaces;
+            entry.assets = dependencies.assets;
+        }
+        Ok(())
+    })
+}
+
 fn find_matching_files(tmp_path: &PathBuf, module_name: &str) -> Vec<PathBuf> {
     walkdir::WalkDir::new(tmp_path)
         .into_iter()
@@ -3,9 +3,11 @@ use std::process::Command;
 
 use crate::cmd::{Execute, Upgrade};
 use crate::config_man::set_version;
+use crate::network;
 
 impl Execute for Upgrade {
     async fn execute(&self) -> Result<()> {
+        network::ensure_online("vpm upgrade")?;
         println!("Upgrading VPM...");
         upgrade_vpm()?;
         let version = get_latest_version().context("Failed to get latest version. Ensure you have an internet connection and try again.")?;
@@ -0,0 +1,16 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::cmd::{Execute, Vendor, VendorAction};
+use crate::vendor;
+
+impl Execute for Vendor {
+    async fn execute(&self) -> Result<()> {
+        match &self.action {
+            VendorAction::Export { output } => vendor::export(Path::new(output)),
+            VendorAction::Import { remove: true, .. } => vendor::forget(),
+            VendorAction::Import { bundle, .. } => vendor::import(Path::new(bundle.as_deref().unwrap_or_default())),
+        }
+    }
+}
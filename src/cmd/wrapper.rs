@@ -0,0 +1,418 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::cmd::include::{lock_path, write_header};
+use crate::index::{declarations, strip_comments, DeclKind};
+use crate::lockfile::{checksum, with_lockfile, EntryKind, LockEntry, Lockfile};
+use crate::toml::{ParamValue, VpmToml};
+
+static MODULE_DECLARATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^[ \t]*(?:module|macromodule)\s+(?:(?:automatic|static)\s+)?([A-Za-z_][A-Za-z0-9_$]*)").unwrap()
+});
+static ENDMODULE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bendmodule\b").unwrap());
+static BODY_PARAMETER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bparameter\b([^;]*);").unwrap());
+// The declared name of a list item: its last identifier, before any unpacked dimensions.
+static ITEM_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"([A-Za-z_][A-Za-z0-9_$]*)\s*(?:\[[^\]]*\]\s*)*$").unwrap());
+
+/// One item of a parameter or port list.
+struct ListItem {
+    name: String,
+    /// The item without its default value, e.g. `parameter integer WIDTH`.
+    declaration: String,
+    default: Option<String>,
+    /// False for `localparam`s, which cannot be overridden.
+    overridable: bool,
+}
+
+/// The declaration of the module a wrapper is generated for.
+struct ModuleHeader {
+    /// Package imports before the parameter list, e.g. `import pkg::*;`.
+    imports: Vec<String>,
+    /// Items of the `#( ... )` parameter port list.
+    parameters: Vec<ListItem>,
+    /// Parameters declared in the module body. They can be overridden, but
+    /// the port list cannot use them.
+    body_parameters: Vec<String>,
+    /// Items of the ANSI port list, e.g. `input wire [WIDTH-1:0] data`.
+    ports: Vec<ListItem>,
+}
+
+/// Brings the wrappers in vpm_modules/ and their vpm.lock entries in line
+/// with the `params` in vpm.toml: generates missing and outdated wrappers and
+/// removes the ones no `params` ask for anymore. With `locked`, vpm.lock must
+/// already record every wrapper as it is generated. Returns how many wrappers
+/// were written.
+pub fn sync(vpm_toml: &VpmToml, lockfile: &mut Lockfile, locked: bool) -> Result<usize> {
+    let mut wanted = Vec::new();
+    // Top modules that are not in vpm_modules/ keep their wrappers until they are restored.
+    let mut absent = Vec::new();
+    for (source, modules) in vpm_toml.get_dependencies() {
+        for module in modules.iter().filter(|m| !m.params.is_empty()) {
+            let Some(top) = lockfile.top_entry(source, &module.top_module) else {
+                continue;
+            };
+            let Ok(contents) = fs::read_to_string(&top.full_path) else {
+                absent.push(top.full_path.clone());
+                continue;
+            };
+            let wrapper = wrap(top, &contents, &module.params)
+                .with_context(|| format!("Failed to generate a wrapper of '{}' with the params in vpm.toml", top.full_path))?;
+            wanted.push(wrapper);
+        }
+    }
+
+    let stale: Vec<String> = lockfile.packages.iter()
+        .filter(|e| e.kind == EntryKind::Wrapper)
+        .filter(|e| !wanted.iter().any(|(w, _)| w.full_path == e.full_path) && !e.parents.iter().any(|p| absent.contains(p)))
+        .map(|e| e.full_path.clone())
+        .collect();
+    for full_path in stale {
+        anyhow::ensure!(!locked, "vpm.lock lists the wrapper '{}', which the params in vpm.toml no longer ask for, and --locked forbids removing it", full_path);
+        lockfile.packages.retain(|e| e.full_path != full_path);
+        let path = Path::new(&full_path);
+        for file in [path.to_path_buf(), path.with_extension("vh"), path.with_extension("svh")] {
+            if file.exists() {
+                fs::remove_file(&file).with_context(|| format!("Failed to remove {}", file.display()))?;
+            }
+        }
+        println!("Removed wrapper {}", full_path);
+    }
+
+    let mut written = 0;
+    for (entry, text) in wanted {
+        if locked {
+            anyhow::ensure!(
+                lockfile.get(&entry.full_path).is_some_and(|e| e.checksum == entry.checksum),
+                "The wrapper '{}' for the params in vpm.toml is missing from vpm.lock or outdated, and --locked forbids changing it",
+                entry.full_path
+            );
+        }
+        if !fs::read(&entry.full_path).is_ok_and(|contents| checksum(&contents) == entry.checksum) {
+            let path = Path::new(&entry.full_path);
+            fs::write(path, &text).with_context(|| format!("Failed to write {}", entry.full_path))?;
+            let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            write_header(path.parent().unwrap_or(Path::new(".")), name, path, &text)?;
+            println!("Generated wrapper {}", entry.full_path);
+            written += 1;
+        }
+        lockfile.upsert(entry);
+    }
+    Ok(written)
+}
+
+/// Regenerates the wrappers after top modules were included or updated.
+pub fn refresh() -> Result<()> {
+    let vpm_toml = VpmToml::load("vpm.toml")?;
+    with_lockfile(|lockfile| sync(&vpm_toml, lockfile, false).map(drop))
+}
+
+/// The lockfile entry and contents of the wrapper of the top module `top`
+/// that sets `params`. It goes next to the top module.
+fn wrap(top: &LockEntry, contents: &str, params: &BTreeMap<String, ParamValue>) -> Result<(LockEntry, String)> {
+    let path = Path::new(&top.full_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let stem = top.renames.get(stem).map(String::as_str).unwrap_or(stem);
+    let modules: Vec<String> = declarations(contents).into_iter()
+        .filter(|(kind, _, _)| *kind == DeclKind::Module)
+        .map(|(_, name, _)| name)
+        .collect();
+    let module = modules.iter().find(|m| *m == stem).or(modules.first())
+        .ok_or_else(|| anyhow::anyhow!("'{}' declares no module", top.full_path))?;
+
+    let (name, text) = generate(contents, module, params)?;
+    let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("v");
+    let entry = LockEntry {
+        full_path: lock_path(&path.with_file_name(format!("{}.{}", name, extension))),
+        source: top.source.clone(),
+        kind: EntryKind::Wrapper,
+        commit: top.commit.clone(),
+        checksum: checksum(text.as_bytes()),
+        parents: vec![top.full_path.clone()],
+        defines: vec![name],
+        submodules: vec![module.clone()],
+        ..Default::default()
+    };
+    Ok((entry, text))
+}
+
+/// A module that instantiates `module` with `params`, named after the module
+/// and the values, e.g. `fifo_w32_d16`. Its parameters and ports are those of
+/// `module`, with the values from `params` as defaults, so the header
+/// generated from it shows them.
+fn generate(contents: &str, module: &str, params: &BTreeMap<String, ParamValue>) -> Result<(String, String)> {
+    let header = parse_header(contents, module)?;
+    // In declaration order, which reads better than the alphabetical order of vpm.toml.
+    let declared: Vec<&str> = header.parameters.iter().map(|p| p.name.as_str())
+        .chain(header.body_parameters.iter().map(String::as_str))
+        .collect();
+    for name in params.keys() {
+        match header.parameters.iter().find(|p| &p.name == name) {
+            Some(parameter) => anyhow::ensure!(parameter.overridable, "'{}' is a localparam of module '{}' and cannot be set", name, module),
+            None => anyhow::ensure!(
+                header.body_parameters.contains(name),
+                "module '{}' has no parameter '{}'. Its parameters are: {}",
+                module, name,
+                header.parameters.iter().filter(|p| p.overridable).map(|p| p.name.as_str())
+                    .chain(header.body_parameters.iter().map(String::as_str))
+                    .collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+    let mut ordered: Vec<(&str, &ParamValue)> = params.iter().map(|(name, value)| (name.as_str(), value)).collect();
+    ordered.sort_by_key(|(name, _)| declared.iter().position(|d| d == name));
+    let name = wrapper_name(module, &ordered);
+
+    let mut text = format!(
+        "// Generated by vpm: {} with {}, as set by `params` in vpm.toml.\n// `vpm fetch` regenerates this file; change vpm.toml instead.\n",
+        module,
+        ordered.iter().map(|(name, value)| format!("{} = {}", name, value)).collect::<Vec<_>>().join(", ")
+    );
+    text.push_str(&format!("module {}", name));
+    for import in &header.imports {
+        text.push_str(&format!("\n    {}", import));
+    }
+    // The parameter list starts a line of its own after imports.
+    let separator = if header.imports.is_empty() { " " } else { "\n" };
+    if !header.parameters.is_empty() {
+        let parameters: Vec<String> = header.parameters.iter()
+            .map(|p| match params.get(&p.name).map(ToString::to_string).or_else(|| p.default.clone()) {
+                Some(value) => format!("{} = {}", p.declaration, value),
+                None => p.declaration.clone(),
+            })
+            .collect();
+        text.push_str(&format!("{}#(\n    {}\n)", separator, parameters.join(",\n    ")));
+    }
+    if header.ports.is_empty() {
+        text.push_str(";\n");
+    } else {
+        let ports: Vec<&str> = header.ports.iter().map(|p| p.declaration.as_str()).collect();
+        text.push_str(&format!(" (\n    {}\n);\n", ports.join(",\n    ")));
+    }
+
+    let overrides: Vec<String> = header.parameters.iter()
+        .filter(|p| p.overridable)
+        .map(|p| format!(".{0}({0})", p.name))
+        .chain(ordered.iter()
+            .filter(|(name, _)| header.body_parameters.iter().any(|b| b == name))
+            .map(|(name, value)| format!(".{}({})", name, value)))
+        .collect();
+    text.push_str(&format!("    {}", module));
+    if !overrides.is_empty() {
+        text.push_str(&format!(" #(\n        {}\n    )", overrides.join(",\n        ")));
+    }
+    if header.ports.is_empty() {
+        text.push_str(&format!(" u_{} ();\nendmodule\n", module));
+    } else {
+        let connections: Vec<String> = header.ports.iter().map(|p| format!(".{0}({0})", p.name)).collect();
+        text.push_str(&format!(" u_{} (\n        {}\n    );\nendmodule\n", module, connections.join(",\n        ")));
+    }
+    Ok((name, text))
+}
+
+/// `module` followed by every parameter's initials and value, e.g.
+/// `fifo_w32_d16` for `WIDTH = 32, DEPTH = 16`. Parameters whose initials
+/// clash are spelled out.
+fn wrapper_name(module: &str, params: &[(&str, &ParamValue)]) -> String {
+    let initials = |name: &str| name.split('_').filter_map(|word| word.chars().next()).collect::<String>().to_lowercase();
+    let mut name = module.to_string();
+    for (param, value) in params {
+        let short = if params.iter().filter(|(other, _)| initials(other) == initials(param)).count() > 1 {
+            param.to_lowercase()
+        } else {
+            initials(param)
+        };
+        let value: String = value.to_string().to_lowercase().chars()
+            .map(|c| match c {
+                '-' => 'm',
+                c if c.is_ascii_alphanumeric() => c,
+                _ => '_',
+            })
+            .collect();
+        name.push_str(&format!("_{}{}", short, value.trim_matches('_')));
+    }
+    name
+}
+
+/// Reads the parameter and port lists of `module` from `contents`.
+fn parse_header(contents: &str, module: &str) -> Result<ModuleHeader> {
+    let code = strip_comments(contents);
+    let start = MODULE_DECLARATION.captures_iter(&code)
+        .find(|caps| &caps[1] == module)
+        .ok_or_else(|| anyhow::anyhow!("module '{}' is not declared", module))?
+        .get(0).unwrap().end();
+
+    let mut imports = Vec::new();
+    let mut rest = skip_imports(&code[start..], &mut imports);
+    let mut parameters = Vec::new();
+    if let Some(after) = rest.strip_prefix('#') {
+        let (list, after) = parenthesized(after.trim_start())
+            .ok_or_else(|| anyhow::anyhow!("the parameter list of module '{}' is not closed", module))?;
+        parameters = list_items(list);
+        rest = skip_imports(after, &mut imports);
+    }
+    let mut ports = Vec::new();
+    if rest.starts_with('(') {
+        let (list, after) = parenthesized(rest)
+            .ok_or_else(|| anyhow::anyhow!("the port list of module '{}' is not closed", module))?;
+        ports = list_items(list);
+        rest = after;
+    }
+    if let Some(first) = ports.first() {
+        anyhow::ensure!(
+            first.declaration != first.name,
+            "module '{}' declares its ports in its body; only modules with ANSI-style port lists can be wrapped", module
+        );
+    }
+
+    let end = ENDMODULE.find(rest).map_or(rest.len(), |m| m.start());
+    let body_parameters = BODY_PARAMETER
+        .captures_iter(&rest[..end])
+        .flat_map(|caps| list_items(&caps[1]))
+        .map(|item| item.name)
+        .collect();
+    Ok(ModuleHeader { imports, parameters, body_parameters, ports })
+}
+
+/// `code` after the package imports before a module's parameter or port
+/// list, which are added to `imports`.
+fn skip_imports<'a>(code: &'a str, imports: &mut Vec<String>) -> &'a str {
+    let mut code = code.trim_start();
+    while let Some(rest) = code.strip_prefix("import") {
+        if !rest.starts_with(char::is_whitespace) {
+            break;
+        }
+        let (import, after) = rest.split_once(';').unwrap_or((rest, ""));
+        imports.push(format!("import {};", import.split_whitespace().collect::<Vec<_>>().join(" ")));
+        code = after.trim_start();
+    }
+    code
+}
+
+/// Splits `code`, which starts with `(`, into what is inside the parentheses
+/// and what follows them.
+fn parenthesized(code: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    let mut in_string = false;
+    for (i, c) in code.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((&code[1..i], code[i + 1..].trim_start()));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The comma-separated items of a parameter or port list. Items without a
+/// `parameter` or `localparam` keyword take the one before them.
+fn list_items(list: &str) -> Vec<ListItem> {
+    let mut overridable = true;
+    let mut items = Vec::new();
+    for item in split_top_level(list, ',') {
+        let item = item.split_whitespace().collect::<Vec<_>>().join(" ");
+        if item.is_empty() {
+            continue;
+        }
+        let parts = split_top_level(&item, '=');
+        let declaration = parts[0].trim().to_string();
+        let default = (parts.len() > 1).then(|| item[parts[0].len() + 1..].trim().to_string());
+        if declaration.starts_with("localparam") {
+            overridable = false;
+        } else if declaration.starts_with("parameter") {
+            overridable = true;
+        }
+        let Some(caps) = ITEM_NAME.captures(&declaration) else {
+            continue;
+        };
+        items.push(ListItem { name: caps[1].to_string(), declaration, default, overridable });
+    }
+    items
+}
+
+/// Splits `code` at every `separator` outside of brackets and strings.
+fn split_top_level(code: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in code.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string => depth -= 1,
+            c if c == separator && depth == 0 && !in_string => {
+                parts.push(&code[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&code[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIFO: &str = "\
+module fifo #(
+    parameter WIDTH = 8,
+    parameter DEPTH = 4,
+    localparam AW = $clog2(DEPTH)
+) (
+    input wire clk,
+    input wire [WIDTH-1:0] din,
+    output reg [WIDTH-1:0] dout
+);
+    parameter MODE = \"SAFE\";
+    parameter signed OFFSET = 0;
+endmodule
+";
+
+    fn params(values: &[(&str, ParamValue)]) -> BTreeMap<String, ParamValue> {
+        values.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    }
+
+    #[test]
+    fn names_and_instantiates_the_wrapper() {
+        let (name, text) = generate(FIFO, "fifo", &params(&[("WIDTH", ParamValue::Integer(32)), ("DEPTH", ParamValue::Integer(16))])).unwrap();
+        assert_eq!(name, "fifo_w32_d16");
+        assert!(text.contains("module fifo_w32_d16 #(\n    parameter WIDTH = 32,\n    parameter DEPTH = 16,\n    localparam AW = $clog2(DEPTH)\n)"));
+        assert!(text.contains("    fifo #(\n        .WIDTH(WIDTH),\n        .DEPTH(DEPTH)\n    ) u_fifo (\n        .clk(clk),\n        .din(din),\n        .dout(dout)\n    );"));
+    }
+
+    #[test]
+    fn rejects_undeclared_and_local_parameters() {
+        let error = generate(FIFO, "fifo", &params(&[("SIZE", ParamValue::Integer(8))])).unwrap_err();
+        assert_eq!(error.to_string(), "module 'fifo' has no parameter 'SIZE'. Its parameters are: WIDTH, DEPTH, MODE, OFFSET");
+        let error = generate(FIFO, "fifo", &params(&[("AW", ParamValue::Integer(3))])).unwrap_err();
+        assert_eq!(error.to_string(), "'AW' is a localparam of module 'fifo' and cannot be set");
+    }
+
+    #[test]
+    fn passes_string_and_negative_values() {
+        let values = params(&[("MODE", ParamValue::Expression("\"FAST\"".to_string())), ("OFFSET", ParamValue::Integer(-4))]);
+        let (name, text) = generate(FIFO, "fifo", &values).unwrap();
+        assert_eq!(name, "fifo_mfast_om4");
+        assert!(text.contains("        .MODE(\"FAST\"),\n        .OFFSET(-4)\n"));
+    }
+
+    #[test]
+    fn spells_out_clashing_initials() {
+        let (eight, half) = (ParamValue::Integer(8), ParamValue::Real(0.5));
+        assert_eq!(wrapper_name("ram", &[("WIDTH", &eight), ("W", &half)]), "ram_width8_w0_5");
+        assert_eq!(wrapper_name("ram", &[("DATA_WIDTH", &eight), ("ENABLE", &ParamValue::Boolean(true))]), "ram_dw8_e1");
+    }
+}
@@ -1,22 +1,23 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use directories::ProjectDirs;
-use reqwest::Client;
 use serde_json::json;
 use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
 use toml_edit::{DocumentMut, Item, Value, Table};
 use uuid::Uuid;
-use machine_uid;
+
+use crate::network;
 
 const POSTHOG_API_KEY: Option<&str> = option_env!("POSTHOG_API_KEY");
 
 pub async fn send_event(command: String) -> Result<()> {
-    if get_analytics()? {
+    if !network::is_offline() && get_analytics()? {
         let uuid = get_uuid()?;
         let version = env!("CARGO_PKG_VERSION").to_string();
         let api_key = POSTHOG_API_KEY.expect("POSTHOG_API_KEY environment variable not set").to_string();
         
-        let client = Client::new();
+        let client = network::client()?;
         let payload = json!({
             "api_key": api_key,
             "event": "user_action",
@@ -27,10 +28,7 @@ pub async fn send_event(command: String) -> Result<()> {
             }
         });
 
-        let _response = client.post("https://us.i.posthog.com/capture/")
-            .json(&payload)
-            .send()
-            .await?;
+        let _response = network::send(client.post("https://us.i.posthog.com/capture/").json(&payload)).await?;
 
         // if !response.status().is_success() {
         //     eprintln!("Failed to send event to PostHog: {}", response.status());
@@ -71,7 +69,7 @@ pub fn create_config() -> Result<()> {
     let options_table = config_doc["options"].as_table_mut().unwrap();
     options_table.insert("analytics", Item::Value(Value::from(true)));
 
-    fs::write(config_path, format!("# Avoid editing this file!\n{}", config_doc.to_string())).expect("Failed to write config.toml");
+    fs::write(config_path, format!("# Avoid editing this file!\n{}", config_doc)).expect("Failed to write config.toml");
     Ok(())
 }
 
@@ -118,4 +116,175 @@ pub fn set_version(version: &str) -> Result<()> {
     config_doc["tool"]["version"] = Item::Value(Value::from(version));
     fs::write(config_path, config_doc.to_string()).expect("Failed to write config.toml");
     Ok(())
-}   
\ No newline at end of file
+}
+
+/// Credentials for one git host, kept in the `[auth."<host>"]` table of the config.
+#[derive(Debug, Default, Clone)]
+pub struct HostAuth {
+    /// Personal access token, used for HTTPS clones and downloads.
+    pub token: Option<String>,
+    /// Private key used for SSH clones.
+    pub ssh_key: Option<String>,
+}
+
+pub fn get_host_auth(host: &str) -> HostAuth {
+    let Some(config_doc) = get_config_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|config| config.parse::<DocumentMut>().ok()) else {
+        return HostAuth::default();
+    };
+    let field = |key: &str| config_doc.get("auth")
+        .and_then(|auth| auth.get(host))
+        .and_then(|table| table.get(key))
+        .and_then(Item::as_str)
+        .filter(|value| !value.is_empty())
+        .map(ToString::to_string);
+    HostAuth { token: field("token"), ssh_key: field("ssh_key") }
+}
+
+/// Sets `key` (`token` or `ssh_key`) for `host`; an empty value removes it.
+pub fn set_host_auth(host: &str, key: &str, value: &str) -> Result<()> {
+    let config_path = get_config_path().context("Failed to get config path")?;
+    if !config_path.exists() {
+        create_config()?;
+    }
+    let config = fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let mut config_doc = config.parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+    if !config_doc.contains_table("auth") {
+        let mut auth = Table::new();
+        auth.set_implicit(true);
+        config_doc.insert("auth", Item::Table(auth));
+    }
+    let auth = config_doc["auth"].as_table_mut()
+        .with_context(|| format!("[auth] in {} is not a table", config_path.display()))?;
+    if !auth.contains_table(host) {
+        auth.insert(host, Item::Table(Table::new()));
+    }
+    let host_table = auth[host].as_table_mut()
+        .with_context(|| format!("[auth.\"{}\"] in {} is not a table", host, config_path.display()))?;
+    if value.is_empty() {
+        host_table.remove(key);
+        if host_table.is_empty() {
+            auth.remove(host);
+        }
+    } else {
+        host_table.insert(key, Item::Value(Value::from(value)));
+    }
+    // The config now holds secrets: write it to a file that only the user can
+    // read from the start, then move that over the old config.
+    let staging = config_path.with_extension("toml.tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let _ = fs::remove_file(&staging);
+    options.open(&staging)
+        .and_then(|mut file| file.write_all(config_doc.to_string().as_bytes()))
+        .and_then(|_| fs::rename(&staging, &config_path))
+        .with_context(|| format!("Failed to write {}", config_path.display()))?;
+    Ok(())
+}
+
+/// URL prefixes and their replacements, from the `[mirrors]` table of the config.
+pub fn get_mirrors() -> Vec<(String, String)> {
+    let Some(config_doc) = get_config_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|config| config.parse::<DocumentMut>().ok()) else {
+        return Vec::new();
+    };
+    config_doc.get("mirrors").and_then(Item::as_table_like).into_iter()
+        .flat_map(|mirrors| mirrors.iter())
+        .filter_map(|(from, to)| to.as_str().map(|to| (from.to_string(), to.to_string())))
+        .collect()
+}
+
+/// Fetches URLs starting with `from` from `to` instead; an empty `to` removes the rule.
+pub fn set_mirror(from: &str, to: &str) -> Result<()> {
+    let config_path = get_config_path().context("Failed to get config path")?;
+    if !config_path.exists() {
+        create_config()?;
+    }
+    let config = fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let mut config_doc = config.parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+    if !config_doc.contains_table("mirrors") {
+        config_doc.insert("mirrors", Item::Table(Table::new()));
+    }
+    let mirrors = config_doc["mirrors"].as_table_mut()
+        .with_context(|| format!("[mirrors] in {} is not a table", config_path.display()))?;
+    if to.is_empty() {
+        mirrors.remove(from);
+    } else {
+        mirrors.insert(from, Item::Value(Value::from(to)));
+    }
+    fs::write(&config_path, config_doc.to_string())
+        .with_context(|| format!("Failed to write {}", config_path.display()))?;
+    Ok(())
+}
+
+/// The `[http]` table of the config. Unset values fall back to the defaults of [`crate::network`].
+#[derive(Debug, Default, Clone)]
+pub struct HttpConfig {
+    /// Proxy for every request, e.g. `http://proxy.corp:3128`.
+    pub proxy: Option<String>,
+    /// PEM file with root certificates to trust in addition to the system ones.
+    pub ca_bundle: Option<String>,
+    /// Seconds to wait for a connection.
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for a response.
+    pub timeout: Option<u64>,
+    /// How often failed requests are retried.
+    pub retries: Option<u32>,
+}
+
+pub fn get_http_config() -> HttpConfig {
+    let Some(config_doc) = get_config_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|config| config.parse::<DocumentMut>().ok()) else {
+        return HttpConfig::default();
+    };
+    let field = |key: &str| config_doc.get("http").and_then(|http| http.get(key)).cloned();
+    let string = |key: &str| field(key).and_then(|item| item.as_str().map(ToString::to_string)).filter(|value| !value.is_empty());
+    let number = |key: &str| field(key).and_then(|item| item.as_integer()).and_then(|value| u64::try_from(value).ok());
+    HttpConfig {
+        proxy: string("proxy"),
+        ca_bundle: string("ca_bundle"),
+        connect_timeout: number("connect_timeout"),
+        timeout: number("timeout"),
+        retries: number("retries").and_then(|value| u32::try_from(value).ok()),
+    }
+}
+
+/// Sets `key` of the `[http]` table; `None` removes it.
+pub fn set_http_option(key: &str, value: Option<Value>) -> Result<()> {
+    let config_path = get_config_path().context("Failed to get config path")?;
+    if !config_path.exists() {
+        create_config()?;
+    }
+    let config = fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let mut config_doc = config.parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+    if !config_doc.contains_table("http") {
+        config_doc.insert("http", Item::Table(Table::new()));
+    }
+    let http = config_doc["http"].as_table_mut()
+        .with_context(|| format!("[http] in {} is not a table", config_path.display()))?;
+    match value {
+        Some(value) => {
+            http.insert(key, Item::Value(value));
+        }
+        None => {
+            http.remove(key);
+        }
+    }
+    fs::write(&config_path, config_doc.to_string())
+        .with_context(|| format!("Failed to write {}", config_path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,382 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use regex::Regex;
+use walkdir::WalkDir;
+
+const HDL_EXTENSIONS: [&str; 4] = ["v", "sv", "vh", "svh"];
+
+static DECLARATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^[ \t]*(?:extern[ \t]+)?(module|macromodule|interface|package)\s+(?:(?:automatic|static)\s+)?([A-Za-z_][A-Za-z0-9_$]*)").unwrap()
+});
+static MODULE_DECLARATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)(^[ \t]*(?:extern[ \t]+)?(?:module|macromodule)\s+(?:(?:automatic|static)\s+)?)([A-Za-z_][A-Za-z0-9_$]*)").unwrap()
+});
+static END_LABEL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\bendmodule\s*:\s*)([A-Za-z_][A-Za-z0-9_$]*)").unwrap());
+// `name inst (`, `name #(`, `name inst [3:0] (` at the start of a statement.
+static INSTANCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)(^[ \t]*|\b(?:begin|else|generate)\s+|[;)]\s*)([A-Za-z_][A-Za-z0-9_$]*)\s*(?:#|[A-Za-z_][A-Za-z0-9_$]*\s*(?:\[[^\]]*\]\s*)?\()").unwrap()
+});
+static INCLUDE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"`include\s+"([^"]+)""#).unwrap());
+// `pkg::` scopes, including `import pkg::*;`.
+static SCOPE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b([A-Za-z_][A-Za-z0-9_$]*)\s*::").unwrap());
+static IDENTIFIER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[A-Za-z_][A-Za-z0-9_$]*\b").unwrap());
+static READMEM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\$readmem[hb]\s*\(\s*"([^"]+)""#).unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeclKind {
+    Module,
+    Interface,
+    Package,
+}
+
+impl fmt::Display for DeclKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeclKind::Module => write!(f, "module"),
+            DeclKind::Interface => write!(f, "interface"),
+            DeclKind::Package => write!(f, "package"),
+        }
+    }
+}
+
+/// A `module`, `interface` or `package` declared somewhere in a repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    pub kind: DeclKind,
+    /// Path of the declaring file, relative to the repository root.
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+/// Every declaration in a checked out repository, by name. Lookups go through
+/// here rather than through file names, since real IP routinely keeps several
+/// modules in one file or names files differently from their modules.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleIndex {
+    declarations: HashMap<String, Vec<Declaration>>,
+}
+
+impl ModuleIndex {
+    /// Scans every Verilog/SystemVerilog source below `root`. Files that
+    /// cannot be read are skipped.
+    pub fn build(root: &Path) -> Self {
+        let mut index = ModuleIndex::default();
+        let files = WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git")
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .filter(|e| e.path().extension().and_then(|ext| ext.to_str()).is_some_and(|ext| HDL_EXTENSIONS.contains(&ext)));
+        for entry in files {
+            let Ok(contents) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            let path = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_path_buf();
+            for (kind, name, line) in declarations(&contents) {
+                index.declarations.entry(name.clone()).or_default().push(Declaration { name, kind, path: path.clone(), line });
+            }
+        }
+        for declarations in index.declarations.values_mut() {
+            declarations.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+        }
+        index
+    }
+
+    /// All declarations of `name` with the given kind, ordered by path.
+    pub fn lookup(&self, name: &str, kind: DeclKind) -> Vec<&Declaration> {
+        self.declarations.get(name).into_iter().flatten()
+            .filter(|d| d.kind == kind)
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str, kind: DeclKind) -> bool {
+        self.declarations.get(name).is_some_and(|d| d.iter().any(|d| d.kind == kind))
+    }
+}
+
+/// Declarations in `contents` as `(kind, name, line)`, ignoring commented-out code.
+pub fn declarations(contents: &str) -> Vec<(DeclKind, String, usize)> {
+    let code = strip_comments(contents);
+    DECLARATION.captures_iter(&code)
+        .filter(|caps| &caps[2] != "class")
+        .map(|caps| {
+            let kind = match &caps[1] {
+                "interface" => DeclKind::Interface,
+                "package" => DeclKind::Package,
+                _ => DeclKind::Module,
+            };
+            let line = code[..caps.get(2).unwrap().start()].matches('\n').count() + 1;
+            (kind, caps[2].to_string(), line)
+        })
+        .collect()
+}
+
+/// Names of the modules, interfaces and packages declared in `contents`.
+pub fn defined_names(contents: &str) -> Vec<String> {
+    declarations(contents).into_iter()
+        .map(|(_, name, _)| name)
+        .collect()
+}
+
+/// Whether `contents` declares at least one module.
+pub fn declares_module(contents: &str) -> bool {
+    declarations(contents).iter().any(|(kind, _, _)| *kind == DeclKind::Module)
+}
+
+/// Renames modules in `contents` by `renames` (old name to new name): their
+/// declarations, `endmodule : name` labels and instantiations. Signals and
+/// other identifiers that happen to share a module's name are left alone, and
+/// so are comments.
+pub fn rename_modules(contents: &str, renames: &BTreeMap<String, String>) -> String {
+    if renames.is_empty() {
+        return contents.to_string();
+    }
+    // Matched against the code without comments, which keeps the byte offsets of `contents`.
+    let code = strip_comments(contents);
+    let mut names: Vec<(usize, usize, &str)> = [&*MODULE_DECLARATION, &*END_LABEL, &*INSTANCE].into_iter()
+        .flat_map(|re| re.captures_iter(&code))
+        .filter_map(|caps| {
+            let name = caps.get(2).unwrap();
+            renames.get(name.as_str()).map(|new| (name.start(), name.end(), new.as_str()))
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let mut renamed = String::with_capacity(contents.len());
+    let mut end = 0;
+    for (start, stop, new) in names {
+        renamed.push_str(&contents[end..start]);
+        renamed.push_str(new);
+        end = stop;
+    }
+    renamed.push_str(&contents[end..]);
+    renamed
+}
+
+/// What a source file refers to besides module instantiations.
+#[derive(Debug, Default)]
+pub struct References {
+    /// `` `include `` paths, as written.
+    pub includes: Vec<String>,
+    /// Packages named by `import pkg::...` or a `pkg::` scope.
+    pub packages: Vec<String>,
+    /// Files loaded with `$readmemh`/`$readmemb`, as written.
+    pub data_files: Vec<String>,
+    /// Every identifier in the file; interface types are the ones the index knows as interfaces.
+    pub identifiers: BTreeSet<String>,
+}
+
+pub fn references(contents: &str) -> References {
+    let code = strip_comments(contents);
+
+    let mut includes: Vec<String> = INCLUDE.captures_iter(&code).map(|c| c[1].to_string()).collect();
+    includes.sort();
+    includes.dedup();
+    let mut packages: Vec<String> = SCOPE.captures_iter(&code)
+        .map(|c| c[1].to_string())
+        .filter(|name| name != "std")
+        .collect();
+    packages.sort();
+    packages.dedup();
+    let mut data_files: Vec<String> = READMEM.captures_iter(&code).map(|c| c[1].to_string()).collect();
+    data_files.sort();
+    data_files.dedup();
+    let identifiers = IDENTIFIER.find_iter(&code).map(|m| m.as_str().to_string()).collect();
+    References { includes, packages, data_files, identifiers }
+}
+
+/// Blanks out `//` and `/* */` comments with spaces, keeping line breaks and
+/// byte offsets so that positions in the result match the original source.
+pub fn strip_comments(contents: &str) -> String {
+    fn blank(out: &mut String, c: char) {
+        if c == '\n' {
+            out.push('\n');
+        } else {
+            out.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        }
+    }
+    let mut out = String::with_capacity(contents.len());
+    let mut chars = contents.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            if c == '\\' {
+                if let Some(escaped) = chars.next() {
+                    out.push(escaped);
+                }
+            } else if c == '"' || c == '\n' {
+                in_string = false;
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                out.push(' ');
+                for c in chars.by_ref() {
+                    blank(&mut out, c);
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                out.push_str("  ");
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    blank(&mut out, c);
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renames(names: &[(&str, &str)]) -> BTreeMap<String, String> {
+        names.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect()
+    }
+
+    #[test]
+    fn renames_declarations_and_end_labels() {
+        let source = "module fifo #(parameter W = 8) (input clk);\nendmodule : fifo\n\nmodule automatic fifo_ctrl;\nendmodule\n";
+        let renamed = rename_modules(source, &renames(&[("fifo", "zip_fifo")]));
+        assert_eq!(renamed, "module zip_fifo #(parameter W = 8) (input clk);\nendmodule : zip_fifo\n\nmodule automatic fifo_ctrl;\nendmodule\n");
+    }
+
+    #[test]
+    fn renames_instances() {
+        let source = "\
+module top;
+  fifo u_plain (.clk(clk));
+  fifo #(.W(16)) u_param (.clk(clk));
+  fifo u_array [3:0] (.clk(clk));
+  if (EN) fifo u_cond (.clk(clk));
+  generate fifo u_gen (.clk(clk)); endgenerate
+endmodule
+";
+        let expected = "\
+module top;
+  zip_fifo u_plain (.clk(clk));
+  zip_fifo #(.W(16)) u_param (.clk(clk));
+  zip_fifo u_array [3:0] (.clk(clk));
+  if (EN) zip_fifo u_cond (.clk(clk));
+  generate zip_fifo u_gen (.clk(clk)); endgenerate
+endmodule
+";
+        assert_eq!(rename_modules(source, &renames(&[("fifo", "zip_fifo")])), expected);
+    }
+
+    #[test]
+    fn leaves_other_identifiers_alone() {
+        let source = "\
+module top;
+  reg fifo;
+  wire [7:0] fifo_data;
+  assign fifo_data = fifo ? 8'h1 : 8'h0;
+  always @(posedge clk) fifo <= 1'b0;
+  // fifo u_commented (.clk(clk));
+  /* module fifo; */
+  initial $display(\"fifo u (\");
+endmodule
+";
+        assert_eq!(rename_modules(source, &renames(&[("fifo", "zip_fifo")])), source);
+    }
+
+    #[test]
+    fn finds_declarations_outside_comments() {
+        let source = "\
+package fifo_pkg;
+endpackage
+// module commented_out;
+/* module also_commented;
+*/
+module fifo #(parameter W = 8) ();
+endmodule
+  extern module ext_decl;
+interface class not_an_interface;
+interface bus_if;
+endinterface
+";
+        assert_eq!(declarations(source), vec![
+            (DeclKind::Package, "fifo_pkg".to_string(), 1),
+            (DeclKind::Module, "fifo".to_string(), 6),
+            (DeclKind::Module, "ext_decl".to_string(), 8),
+            (DeclKind::Interface, "bus_if".to_string(), 10),
+        ]);
+        assert!(declares_module(source));
+        assert!(!declares_module("package p; endpackage\n"));
+    }
+
+    #[test]
+    fn collects_references() {
+        let source = "\
+`include \"defs.vh\"
+`include \"defs.vh\"
+import axi_pkg::*;
+module top (bus_if.master bus);
+  localparam W = cfg_pkg::WIDTH;
+  int q[$] = std::randomize();
+  initial $readmemh(\"rom.hex\", mem);
+  // `include \"commented.vh\"
+endmodule
+";
+        let references = references(source);
+        assert_eq!(references.includes, ["defs.vh"]);
+        assert_eq!(references.packages, ["axi_pkg", "cfg_pkg"]);
+        assert_eq!(references.data_files, ["rom.hex"]);
+        assert!(references.identifiers.contains("bus_if"));
+        assert!(!references.identifiers.contains("commented"));
+    }
+
+    #[test]
+    fn indexes_a_repository() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("rtl/core")).unwrap();
+        fs::create_dir_all(root.path().join(".git")).unwrap();
+        fs::write(root.path().join("rtl/core/fifo.sv"), "module fifo;\nendmodule\nmodule fifo_ctrl;\nendmodule\n").unwrap();
+        fs::write(root.path().join("rtl/fifo.v"), "\n\nmodule fifo;\nendmodule\n").unwrap();
+        fs::write(root.path().join("rtl/pkg.svh"), "package fifo;\nendpackage\n").unwrap();
+        fs::write(root.path().join("rtl/notes.txt"), "module not_hdl;\n").unwrap();
+        fs::write(root.path().join(".git/hook.v"), "module in_git;\nendmodule\n").unwrap();
+
+        let index = ModuleIndex::build(root.path());
+        let fifos: Vec<(PathBuf, usize)> = index.lookup("fifo", DeclKind::Module).into_iter()
+            .map(|d| (d.path.clone(), d.line))
+            .collect();
+        assert_eq!(fifos, [(PathBuf::from("rtl/core/fifo.sv"), 1), (PathBuf::from("rtl/fifo.v"), 3)]);
+        assert_eq!(index.lookup("fifo", DeclKind::Package)[0].path, PathBuf::from("rtl/pkg.svh"));
+        assert!(index.contains("fifo_ctrl", DeclKind::Module));
+        assert!(!index.contains("fifo_ctrl", DeclKind::Interface));
+        assert!(!index.contains("not_hdl", DeclKind::Module));
+        assert!(!index.contains("in_git", DeclKind::Module));
+    }
+
+    #[test]
+    fn keeps_offsets_when_stripping_comments() {
+        let source = "a // übersicht\nb /* ä\n */ c \"// kept\"";
+        let stripped = strip_comments(source);
+        assert_eq!(stripped.len(), source.len());
+        assert_eq!(stripped.lines().count(), source.lines().count());
+        assert_eq!(stripped.split_whitespace().collect::<Vec<_>>(), ["a", "b", "c", "\"//", "kept\""]);
+    }
+}
@@ -1,5 +1,4 @@
-use anyhow::Result;
-use machine_uid;
+use anyhow::{Context, Result};
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, ACCEPT, CONTENT_TYPE};
 use serde_json::Value;
 use std::fs;
@@ -8,10 +7,39 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use rpassword::read_password;
 
 use crate::config_man::{create_config, get_config_path};
+use crate::network;
 
 const API_URL: &str = "https://api.keygen.sh/v1";
 const ACCOUNT_ID: Option<&str> = option_env!("KEYGEN_ACCOUNT_ID");
 const TOKEN: Option<&str> = option_env!("KEYGEN_TOKEN");
+/// How long a license checked online stays valid for offline runs.
+const OFFLINE_GRACE_PERIOD: u64 = 7 * 86400;
+
+/// Seconds since the Unix epoch at which the license was last checked online.
+fn last_check() -> Result<Option<u64>> {
+    let config_path = get_config_path().ok_or_else(|| anyhow::anyhow!("Failed to get config path"))?;
+    let last_check_file = config_path.with_file_name(".last_check");
+    if !last_check_file.exists() {
+        return Ok(None);
+    }
+    let last_check = fs::read_to_string(&last_check_file)?.trim().parse::<u64>()
+        .with_context(|| format!("{} is corrupted", last_check_file.display()))?;
+    Ok(Some(last_check))
+}
+
+/// Offline runs cannot reach the license server; they are allowed as long as
+/// the license was checked online within the grace period.
+pub fn check_license_offline() -> Result<()> {
+    let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    match last_check()? {
+        Some(last_check) if current_time.saturating_sub(last_check) < OFFLINE_GRACE_PERIOD => Ok(()),
+        Some(last_check) => anyhow::bail!(
+            "vpm is offline and the license was last checked {} days ago; offline use is allowed for {} days after a check. Run vpm once with network access.",
+            current_time.saturating_sub(last_check) / 86400, OFFLINE_GRACE_PERIOD / 86400
+        ),
+        None => anyhow::bail!("vpm is offline and the license has never been checked. Run vpm once with network access."),
+    }
+}
 
 pub async fn check_license() -> Result<()> {
     let config_path = get_config_path().ok_or_else(|| anyhow::anyhow!("Failed to get config path"))?;
@@ -21,15 +49,13 @@ pub async fn check_license() -> Result<()> {
     let last_check_file = config_path.with_file_name(".last_check");
 
     let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    let should_check = if last_check_file.exists() {
-        let last_check = fs::read_to_string(&last_check_file)?.parse::<u64>()?;
-        current_time - last_check >= 86400 // 24 hours in seconds
-    } else {
-        true
+    let should_check = match last_check()? {
+        Some(last_check) => current_time.saturating_sub(last_check) >= 86400, // 24 hours in seconds
+        None => true,
     };
 
     if should_check {
-        let client = reqwest::Client::new();
+        let client = network::client()?;
         let mut headers = HeaderMap::new();
         headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", TOKEN.unwrap()))?);
         headers.insert(ACCEPT, HeaderValue::from_static("application/vnd.api+json"));
@@ -97,12 +123,10 @@ async fn validate_license(
         }
     });
 
-    let response = client
+    let response = network::send(client
         .post(&url)
         .headers(headers.clone())
-        .json(&body)
-        .send()
-        .await?;
+        .json(&body)).await?;
 
     let status = response.status();
     let json: Value = response.json().await?;
@@ -141,12 +165,10 @@ async fn activate_license(
         }
     });
 
-    let response = client
+    let response = network::send(client
         .post(&url)
         .headers(headers.clone())
-        .json(&body)
-        .send()
-        .await?;
+        .json(&body)).await?;
   
     let status = response.status();
     let text = response.text().await?;
@@ -168,12 +190,10 @@ async fn deactivate_license(
     let machine_fingerprint = machine_uid::get()?;
     let url = format!("{}/accounts/{}/machines/{}", API_URL, ACCOUNT_ID.unwrap(), machine_fingerprint);
 
-    let response = client
+    let response = network::send(client
         .delete(&url)
         .headers(headers.clone())
-        .bearer_auth(license_id)
-        .send()
-        .await?;
+        .bearer_auth(license_id)).await?;
 
     let status = response.status();
     if status.is_success() {
@@ -0,0 +1,326 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+
+pub const LOCKFILE_VERSION: u32 = 1;
+
+/// Typed model of vpm.lock. Every file vpm copies into `vpm_modules/` gets
+/// exactly one `[[package]]` entry, keyed by its path in the project.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockEntry>,
+}
+
+/// What a locked file is. HDL files get headers and have their dependencies
+/// followed; assets (memory images, constraints, scripts) are copied verbatim.
+/// Local files are the project's own sources, recorded by `vpm init --scan`
+/// or moved into vpm_modules/ by `vpm restructure`, so that their modules and
+/// dependencies are known; they are never restored.
+/// Wrappers are generated from a top module and the `params` set for it in
+/// vpm.toml; they are regenerated rather than restored.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    #[default]
+    Hdl,
+    Asset,
+    Local,
+    Wrapper,
+}
+
+impl EntryKind {
+    /// Whether files of this kind are copied from their source, and restored from it.
+    pub fn is_copied(self) -> bool {
+        matches!(self, EntryKind::Hdl | EntryKind::Asset)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LockEntry {
+    /// Where the file lives in the project, e.g. `vpm_modules/pfcache/rtl/pfcache.v`.
+    pub full_path: String,
+    /// Repository the file was taken from. For local files, the origin given
+    /// to `vpm restructure`, or empty.
+    pub source: String,
+    #[serde(default)]
+    pub kind: EntryKind,
+    /// Commit of `source` the file was taken at.
+    #[serde(default)]
+    pub commit: String,
+    /// For top modules, the revision vpm.toml asks for, e.g. `tag v1` or
+    /// `version ^1.2`; `commit` is what it resolved to.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub revision: String,
+    /// Path of the file inside the upstream repository.
+    #[serde(default)]
+    pub path: String,
+    /// `sha256:<hex>` of the file as copied into the project, i.e. of the
+    /// upstream contents after `renames` are applied.
+    #[serde(default)]
+    pub checksum: String,
+    /// `full_path`s of the entries that instantiate this file. Empty for top modules.
+    #[serde(default)]
+    pub parents: Vec<String>,
+    /// Names of the modules, interfaces and packages declared in this file. A
+    /// parent's dependency was resolved to this file when its name is listed here.
+    #[serde(default)]
+    pub defines: Vec<String>,
+    /// Names of the modules this file instantiates.
+    #[serde(default)]
+    pub submodules: Vec<String>,
+    /// `` `include `` paths of this file, as written.
+    #[serde(default)]
+    pub includes: Vec<String>,
+    /// Packages this file imports.
+    #[serde(default)]
+    pub packages: Vec<String>,
+    /// Interface types this file uses.
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    /// Data files this file loads with `$readmemh`/`$readmemb`, as written.
+    #[serde(default)]
+    pub assets: Vec<String>,
+    /// Modules renamed in this file when it was included with `--namespace`,
+    /// from their upstream name to their name in the project. Restoring the
+    /// file applies the same renaming.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub renames: BTreeMap<String, String>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Lockfile {
+            version: LOCKFILE_VERSION,
+            packages: Vec::new(),
+        }
+    }
+}
+
+impl Lockfile {
+    pub fn load(filepath: &str) -> Result<Self> {
+        if !Path::new(filepath).exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(filepath)
+            .with_context(|| format!("Failed to read {}", filepath))?;
+        let lockfile: Lockfile = toml_edit::de::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Invalid lockfile {}", filepath))?;
+        anyhow::ensure!(
+            lockfile.version <= LOCKFILE_VERSION,
+            "{} was written by a newer version of vpm (lockfile version {})", filepath, lockfile.version
+        );
+        Ok(lockfile)
+    }
+
+    pub fn save(&mut self, filepath: &str) -> Result<()> {
+        self.version = LOCKFILE_VERSION;
+        self.packages.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+        let content = toml_edit::ser::to_string_pretty(self)?;
+        fs::write(filepath, content).with_context(|| format!("Failed to write {}", filepath))?;
+        Ok(())
+    }
+
+    pub fn get(&self, full_path: &str) -> Option<&LockEntry> {
+        self.packages.iter().find(|e| e.full_path == full_path)
+    }
+
+    pub fn get_mut(&mut self, full_path: &str) -> Option<&mut LockEntry> {
+        self.packages.iter_mut().find(|e| e.full_path == full_path)
+    }
+
+    /// The entry of the top module `top_module` from `source`. Restructured
+    /// top modules are local entries that record their origin as `source`.
+    pub fn top_entry(&self, source: &str, top_module: &str) -> Option<&LockEntry> {
+        self.packages.iter()
+            .filter(|e| matches!(e.kind, EntryKind::Hdl | EntryKind::Local))
+            .find(|e| e.parents.is_empty() && e.source == source && file_name(&e.full_path) == file_name(top_module))
+    }
+
+    /// Inserts `entry`, or replaces the entry with the same `full_path` while
+    /// keeping the parents already recorded for it.
+    pub fn upsert(&mut self, mut entry: LockEntry) {
+        if let Some(existing) = self.get_mut(&entry.full_path) {
+            for parent in std::mem::take(&mut existing.parents) {
+                if !entry.parents.contains(&parent) {
+                    entry.parents.push(parent);
+                }
+            }
+            entry.parents.sort();
+            *existing = entry;
+        } else {
+            entry.parents.sort();
+            self.packages.push(entry);
+        }
+    }
+
+    pub fn add_parent(&mut self, full_path: &str, parent: &str) {
+        if let Some(entry) = self.get_mut(full_path) {
+            if !entry.parents.iter().any(|p| p == parent) {
+                entry.parents.push(parent.to_string());
+                entry.parents.sort();
+            }
+        }
+    }
+
+    /// Removes the entry for `full_path` together with every submodule entry
+    /// that was only reachable through it. Returns the removed entries.
+    pub fn remove(&mut self, full_path: &str) -> Vec<LockEntry> {
+        let mut removed = Vec::new();
+        let mut pending = vec![full_path.to_string()];
+        while let Some(path) = pending.pop() {
+            let Some(index) = self.packages.iter().position(|e| e.full_path == path) else {
+                continue;
+            };
+            removed.push(self.packages.remove(index));
+            for entry in &mut self.packages {
+                if entry.parents.contains(&path) {
+                    entry.parents.retain(|p| p != &path);
+                    if entry.parents.is_empty() {
+                        pending.push(entry.full_path.clone());
+                    }
+                }
+            }
+        }
+        removed
+    }
+
+    /// Names declared by more than one file of the project, with the files
+    /// declaring them. Copies of the same upstream file at the same commit, as
+    /// when two top modules share a submodule, are counted once.
+    pub fn duplicate_names(&self) -> BTreeMap<&str, Vec<&LockEntry>> {
+        let mut declaring: BTreeMap<&str, Vec<&LockEntry>> = BTreeMap::new();
+        for entry in self.packages.iter().filter(|e| e.kind != EntryKind::Asset) {
+            for name in &entry.defines {
+                let files = declaring.entry(name).or_default();
+                let is_copy = |other: &&LockEntry| {
+                    entry.kind != EntryKind::Local && other.source == entry.source && other.path == entry.path && other.commit == entry.commit
+                };
+                if !files.iter().any(is_copy) {
+                    files.push(entry);
+                }
+            }
+        }
+        declaring.retain(|_, files| files.len() > 1);
+        declaring
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+pub fn checksum(contents: &[u8]) -> String {
+    let digest = Sha256::digest(contents);
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256:{}", hex)
+}
+
+/// Loads vpm.lock, applies `f` and writes the result back.
+pub fn with_lockfile<T>(f: impl FnOnce(&mut Lockfile) -> Result<T>) -> Result<T> {
+    let mut lockfile = Lockfile::load("vpm.lock")?;
+    let result = f(&mut lockfile)?;
+    lockfile.save("vpm.lock")?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(full_path: &str, source: &str, path: &str) -> LockEntry {
+        LockEntry {
+            full_path: full_path.to_string(),
+            source: source.to_string(),
+            commit: "0123456789abcdef0123456789abcdef01234567".to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vpm.lock");
+        let path = path.to_str().unwrap();
+
+        let mut top = entry("vpm_modules/top/rtl/top.v", "https://github.com/a/top", "rtl/top.v");
+        top.checksum = checksum(b"module top; endmodule\n");
+        top.submodules = vec!["zip_fifo".to_string()];
+        top.renames = BTreeMap::from([("fifo".to_string(), "zip_fifo".to_string())]);
+        let mut fifo = entry("vpm_modules/top/rtl/fifo.v", "https://github.com/a/top", "rtl/fifo.v");
+        fifo.parents = vec![top.full_path.clone()];
+        let mut image = entry("vpm_modules/top/rom.hex", "https://github.com/a/top", "rom.hex");
+        image.kind = EntryKind::Asset;
+        image.parents = vec![top.full_path.clone()];
+        let mut lockfile = Lockfile { packages: vec![top, fifo, image], ..Default::default() };
+        lockfile.save(path).unwrap();
+
+        let loaded = Lockfile::load(path).unwrap();
+        assert_eq!(loaded.version, LOCKFILE_VERSION);
+        // Saving orders the entries by path.
+        assert_eq!(loaded.packages, lockfile.packages);
+        assert_eq!(loaded.packages[0].full_path, "vpm_modules/top/rom.hex");
+        assert!(!fs::read_to_string(path).unwrap().contains("renames = {}"));
+    }
+
+    #[test]
+    fn refuses_lockfiles_of_newer_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vpm.lock");
+        fs::write(&path, format!("version = {}\n", LOCKFILE_VERSION + 1)).unwrap();
+        assert!(Lockfile::load(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn keeps_parents_of_paths_sharing_a_prefix_apart() {
+        let mut lockfile = Lockfile::default();
+        lockfile.upsert(entry("vpm_modules/fifo/rtl/fifo.v", "https://github.com/a/a", "fifo.v"));
+        lockfile.upsert(entry("vpm_modules/fifo_async/rtl/fifo_async.v", "https://github.com/a/a", "fifo_async.v"));
+        for top in ["vpm_modules/fifo/rtl/fifo.v", "vpm_modules/fifo_async/rtl/fifo_async.v"] {
+            let mut ram = entry("vpm_modules/fifo/rtl/ram.v", "https://github.com/a/a", "ram.v");
+            ram.parents = vec![top.to_string()];
+            lockfile.upsert(ram);
+        }
+        assert_eq!(lockfile.get_mut("vpm_modules/fifo/rtl/ram.v").unwrap().parents.len(), 2);
+
+        // The shared submodule stays until its last parent is removed.
+        let removed = lockfile.remove("vpm_modules/fifo/rtl/fifo.v");
+        assert_eq!(removed.len(), 1);
+        assert_eq!(lockfile.get_mut("vpm_modules/fifo/rtl/ram.v").unwrap().parents, ["vpm_modules/fifo_async/rtl/fifo_async.v"]);
+        let removed = lockfile.remove("vpm_modules/fifo_async/rtl/fifo_async.v");
+        assert_eq!(removed.len(), 2);
+        assert!(lockfile.packages.is_empty());
+    }
+
+    #[test]
+    fn finds_names_declared_by_several_files() {
+        let declaring = |full_path: &str, source: &str, path: &str, defines: &[&str]| LockEntry {
+            defines: defines.iter().map(ToString::to_string).collect(),
+            ..entry(full_path, source, path)
+        };
+        let mut image = declaring("vpm_modules/b/fifo.hex", "https://github.com/b/b", "fifo.hex", &["fifo"]);
+        image.kind = EntryKind::Asset;
+        let lockfile = Lockfile {
+            packages: vec![
+                declaring("vpm_modules/a/rtl/fifo.v", "https://github.com/a/a", "rtl/fifo.v", &["fifo", "fifo_pkg"]),
+                // The same upstream file copied for a second top module.
+                declaring("vpm_modules/c/rtl/fifo.v", "https://github.com/a/a", "rtl/fifo.v", &["fifo", "fifo_pkg"]),
+                declaring("vpm_modules/b/rtl/fifo.sv", "https://github.com/b/b", "fifo.sv", &["fifo"]),
+                declaring("vpm_modules/b/rtl/ram.sv", "https://github.com/b/b", "ram.sv", &["ram"]),
+                image,
+            ],
+            ..Default::default()
+        };
+        let duplicates = lockfile.duplicate_names();
+        assert_eq!(duplicates.keys().collect::<Vec<_>>(), [&"fifo"]);
+        let files: Vec<&str> = duplicates["fifo"].iter().map(|e| e.full_path.as_str()).collect();
+        assert_eq!(files, ["vpm_modules/a/rtl/fifo.v", "vpm_modules/b/rtl/fifo.sv"]);
+    }
+}
@@ -1,21 +1,31 @@
 mod cmd;
 mod error;
 mod toml;
+mod lockfile;
+mod git;
+mod archive;
+mod cache;
+mod network;
+mod vendor;
+mod index;
 mod config_man;
+mod project;
 mod license;
 
 use std::env;
 use std::io::{self, Write};
+use std::path::Path;
 use std::process::ExitCode;
 use std::fs;
 
 use clap::Parser;
 
-use crate::cmd::{Cmd, Execute};
+use crate::cmd::{Cli, Cmd, Execute};
+use crate::toml::VpmToml;
 use crate::error::SilentExit;
 
 use crate::config_man::{get_config_path, create_config, set_analytics};
-use crate::license::check_license;
+use crate::license::{check_license, check_license_offline};
 
 #[tokio::main]
 pub async fn main() -> ExitCode {
@@ -23,8 +33,23 @@ pub async fn main() -> ExitCode {
     // Forcibly disable backtraces.
     env::remove_var("RUST_LIB_BACKTRACE");
     env::remove_var("RUST_BACKTRACE");
-    
-    if let Err(e) = check_license().await {
+
+    let mut cli = Cli::parse();
+    // --frozen forbids the network for the whole run, license check included.
+    let frozen = matches!(&cli.command, Cmd::Fetch(fetch) if fetch.frozen);
+    network::init(cli.offline || frozen);
+    let manifest_path = cli.manifest_path.as_deref().map(Path::new);
+    // A new project starts where vpm init runs, even inside another project.
+    let discover = !matches!(cli.command, Cmd::Init(_));
+    if let Err(e) = project::enter(manifest_path, cli.directory.as_deref().map(Path::new), discover) {
+        eprintln!("vpm: {e:?}");
+        return ExitCode::FAILURE;
+    }
+    cli.command.rebase_paths();
+
+    // Offline runs rely on the last online check, for a grace period.
+    let license = if network::is_offline() { check_license_offline() } else { check_license().await };
+    if let Err(e) = license {
         eprintln!("License check failed: {}", e);
         eprintln!("Check your license and try again. Contact team@getinstachip.com for assistance.");
         return ExitCode::FAILURE;
@@ -71,7 +96,16 @@ pub async fn main() -> ExitCode {
         fs::write(flag_file, "").unwrap();
     }
 
-    match Cmd::parse().execute().await {
+    // Keys this version does not know are ignored; say so once per run.
+    if Path::new("vpm.toml").exists() {
+        if let Ok(vpm_toml) = VpmToml::load("vpm.toml") {
+            for warning in vpm_toml.warnings() {
+                eprintln!("Warning: vpm.toml {}", warning);
+            }
+        }
+    }
+
+    match cli.command.execute().await {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => match e.downcast::<SilentExit>() {
             Ok(SilentExit { code }) => code.into(),
@@ -0,0 +1,177 @@
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::{Certificate, Proxy, StatusCode};
+
+use crate::config_man::{get_http_config, HttpConfig};
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Turns on offline mode when `--offline` was passed or `VPM_OFFLINE` is set
+/// to anything but `0` or `false`.
+pub fn init(offline: bool) {
+    let from_env = std::env::var("VPM_OFFLINE")
+        .is_ok_and(|value| !matches!(value.trim().to_lowercase().as_str(), "" | "0" | "false"));
+    OFFLINE.store(offline || from_env, Ordering::Relaxed);
+}
+
+/// Whether vpm must not access the network. Dependencies are then served from
+/// the repository cache only.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Fails right away in offline mode; `what` names what needed the network.
+pub fn ensure_online(what: &str) -> Result<()> {
+    anyhow::ensure!(!is_offline(), "{} needs network access, but vpm is offline (--offline or VPM_OFFLINE)", what);
+    Ok(())
+}
+
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_TIMEOUT: u64 = 60;
+const DEFAULT_RETRIES: u32 = 3;
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+static BLOCKING_CLIENT: OnceLock<reqwest::blocking::Client> = OnceLock::new();
+
+fn certificates(config: &HttpConfig) -> Result<Vec<Certificate>> {
+    let Some(path) = &config.ca_bundle else {
+        return Ok(Vec::new());
+    };
+    let pem = fs::read(path).with_context(|| format!("Failed to read CA bundle {}", path))?;
+    Certificate::from_pem_bundle(&pem).with_context(|| format!("{} is not a PEM certificate bundle", path))
+}
+
+fn proxy(config: &HttpConfig) -> Result<Option<Proxy>> {
+    config.proxy.as_deref()
+        .map(|url| Proxy::all(url).with_context(|| format!("Invalid proxy URL '{}'", url)))
+        .transpose()
+}
+
+/// The HTTP client every request of vpm goes through, configured from the
+/// `[http]` table of the vpm config: proxy, extra root certificates and timeouts.
+pub fn client() -> Result<reqwest::Client> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let config = get_http_config();
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT)))
+        .read_timeout(Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT)));
+    if let Some(proxy) = proxy(&config)? {
+        builder = builder.proxy(proxy);
+    }
+    for certificate in certificates(&config)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    let client = builder.build().context("Failed to set up the HTTP client")?;
+    Ok(CLIENT.get_or_init(|| client).clone())
+}
+
+/// Blocking counterpart of [`client`], for code that runs outside the async
+/// runtime's worker threads.
+pub fn blocking_client() -> Result<reqwest::blocking::Client> {
+    if let Some(client) = BLOCKING_CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let config = get_http_config();
+    let mut builder = reqwest::blocking::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT)))
+        .timeout(Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT)));
+    if let Some(proxy) = proxy(&config)? {
+        builder = builder.proxy(proxy);
+    }
+    for certificate in certificates(&config)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    let client = builder.build().context("Failed to set up the HTTP client")?;
+    Ok(BLOCKING_CLIENT.get_or_init(|| client).clone())
+}
+
+fn should_retry(status: Option<StatusCode>, error: Option<&reqwest::Error>) -> bool {
+    match (status, error) {
+        (Some(status), _) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        (None, Some(error)) => error.is_connect() || error.is_timeout(),
+        (None, None) => false,
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(500 * 2u64.pow(attempt))
+}
+
+/// Sends `request`, retrying connection failures, timeouts, 429 and 5xx
+/// responses with exponential backoff, up to `retries` times.
+pub async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+    ensure_online("This request")?;
+    let retries = get_http_config().retries.unwrap_or(DEFAULT_RETRIES);
+    let mut attempt = 0;
+    loop {
+        // Requests with streaming bodies cannot be cloned, and so are not retried.
+        let Some(this) = request.try_clone() else {
+            return Ok(request.send().await?);
+        };
+        let result = this.send().await;
+        let status = result.as_ref().ok().map(|response| response.status());
+        if attempt >= retries || !should_retry(status, result.as_ref().err()) {
+            return Ok(result?);
+        }
+        tokio::time::sleep(backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// Blocking counterpart of [`send`].
+pub fn send_blocking(request: reqwest::blocking::RequestBuilder) -> Result<reqwest::blocking::Response> {
+    ensure_online("This request")?;
+    let retries = get_http_config().retries.unwrap_or(DEFAULT_RETRIES);
+    let mut attempt = 0;
+    loop {
+        let Some(this) = request.try_clone() else {
+            return Ok(request.send()?);
+        };
+        let result = this.send();
+        let status = result.as_ref().ok().map(|response| response.status());
+        if attempt >= retries || !should_retry(status, result.as_ref().err()) {
+            return Ok(result?);
+        }
+        std::thread::sleep(backoff(attempt));
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_server_errors_and_rate_limits_only() {
+        assert!(should_retry(Some(StatusCode::INTERNAL_SERVER_ERROR), None));
+        assert!(should_retry(Some(StatusCode::BAD_GATEWAY), None));
+        assert!(should_retry(Some(StatusCode::TOO_MANY_REQUESTS), None));
+        assert!(!should_retry(Some(StatusCode::OK), None));
+        assert!(!should_retry(Some(StatusCode::NOT_FOUND), None));
+        assert!(!should_retry(Some(StatusCode::UNAUTHORIZED), None));
+        assert!(!should_retry(None, None));
+    }
+
+    #[test]
+    fn retries_connection_failures_but_not_bad_requests() {
+        let client = reqwest::blocking::Client::new();
+        // Nothing listens on port 1, so the connection is refused.
+        let refused = client.get("http://127.0.0.1:1/").send().unwrap_err();
+        assert!(should_retry(None, Some(&refused)));
+        let invalid = client.get("not a url").send().unwrap_err();
+        assert!(!should_retry(None, Some(&invalid)));
+    }
+
+    #[test]
+    fn doubles_the_delay_between_attempts() {
+        assert_eq!(backoff(0), Duration::from_millis(500));
+        assert_eq!(backoff(1), Duration::from_millis(1000));
+        assert_eq!(backoff(3), Duration::from_millis(4000));
+    }
+}
@@ -0,0 +1,125 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+
+pub const MANIFEST: &str = "vpm.toml";
+
+/// The directory vpm was started in, after `-C`.
+static INVOKED: OnceLock<PathBuf> = OnceLock::new();
+
+/// The closest directory at or above `start` that has a vpm.toml.
+pub fn find_root(start: &Path) -> Option<PathBuf> {
+    start.ancestors()
+        .find(|dir| dir.join(MANIFEST).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Moves into the project root so that vpm.toml, vpm.lock and vpm_modules/
+/// resolve from there, wherever in the project vpm was started.
+///
+/// `directory` (`-C`) changes the directory first, as if vpm was started in
+/// it. The root is then the directory of `manifest_path` if one was given,
+/// else the closest directory with a vpm.toml if `discover` is set, else the
+/// current directory.
+pub fn enter(manifest_path: Option<&Path>, directory: Option<&Path>, discover: bool) -> Result<PathBuf> {
+    if let Some(directory) = directory {
+        env::set_current_dir(directory)
+            .with_context(|| format!("Failed to change to directory '{}'", directory.display()))?;
+    }
+    let invoked = env::current_dir().context("Failed to read the current directory")?;
+
+    let root = match manifest_path {
+        Some(manifest_path) => {
+            anyhow::ensure!(
+                manifest_path.file_name().is_some_and(|name| name == MANIFEST),
+                "--manifest-path must point to a {} file, got '{}'", MANIFEST, manifest_path.display()
+            );
+            let dir = invoked.join(manifest_path.parent().unwrap_or(Path::new("")));
+            dir.canonicalize()
+                .with_context(|| format!("Failed to find the directory of '{}'", manifest_path.display()))?
+        }
+        None if discover => find_root(&invoked).unwrap_or_else(|| invoked.clone()),
+        None => invoked.clone(),
+    };
+    env::set_current_dir(&root)
+        .with_context(|| format!("Failed to change to project root '{}'", root.display()))?;
+    let _ = INVOKED.set(invoked);
+    Ok(root)
+}
+
+/// Rewrites `path`, given relative to the directory vpm was started in, so it
+/// resolves from the project root. Paths inside the project stay relative, so
+/// they are recorded in vpm.toml and vpm.lock the same way from any directory.
+pub fn from_invocation(path: &str) -> String {
+    let Some(invoked) = INVOKED.get() else {
+        return path.to_string();
+    };
+    let root = env::current_dir().unwrap_or_default();
+    relative_to(&root, invoked, path)
+}
+
+/// `path`, given relative to `invoked`, as a path from `root`; absolute if it
+/// lies outside of `root`.
+fn relative_to(root: &Path, invoked: &Path, path: &str) -> String {
+    let absolute = normalize(&invoked.join(path));
+    match absolute.strip_prefix(normalize(root)) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Ok(relative) => relative.to_string_lossy().to_string(),
+        Err(_) => absolute.to_string_lossy().to_string(),
+    }
+}
+
+/// Whether `path`, given relative to the directory vpm was started in, exists.
+pub fn exists_from_invocation(path: &str) -> bool {
+    INVOKED.get().map_or(Path::new(path).exists(), |invoked| invoked.join(path).exists())
+}
+
+/// Removes `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_without_the_filesystem() {
+        assert_eq!(normalize(Path::new("/work/proj/./rtl/../sim/tb.sv")), PathBuf::from("/work/proj/sim/tb.sv"));
+        assert_eq!(normalize(Path::new("/work/proj/../../..")), PathBuf::from("/"));
+        assert_eq!(normalize(Path::new("rtl/./core/")), PathBuf::from("rtl/core"));
+    }
+
+    #[test]
+    fn rebases_paths_onto_the_project_root() {
+        let root = Path::new("/work/proj");
+        assert_eq!(relative_to(root, Path::new("/work/proj/rtl/core"), "fifo.v"), "rtl/core/fifo.v");
+        assert_eq!(relative_to(root, Path::new("/work/proj/rtl/core"), "../../sim/tb.sv"), "sim/tb.sv");
+        assert_eq!(relative_to(root, Path::new("/work/proj/rtl"), ".."), ".");
+        assert_eq!(relative_to(root, Path::new("/work/proj/rtl"), "../../other/ip.v"), "/work/other/ip.v");
+        assert_eq!(relative_to(root, Path::new("/work/proj/rtl"), "/opt/ip/fifo.v"), "/opt/ip/fifo.v");
+    }
+
+    #[test]
+    fn finds_the_closest_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("ip/rtl/core");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(dir.path().join(MANIFEST), "").unwrap();
+        assert_eq!(find_root(&nested), Some(dir.path().to_path_buf()));
+        std::fs::write(dir.path().join("ip").join(MANIFEST), "").unwrap();
+        assert_eq!(find_root(&nested), Some(dir.path().join("ip")));
+    }
+}
@@ -1,24 +1,116 @@
 use serde::{Deserialize, Serialize};
-use std::fs::{OpenOptions, read_to_string};
-use std::io::Write;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
 use std::path::Path;
-use std::collections::HashSet;
-use anyhow::Result;
-use toml_edit::{Array, DocumentMut, InlineTable, Item, Table, Value};
+use anyhow::{Context, Result};
+use toml_edit::{Array, DocumentMut, ImDocument, InlineTable, Item, Table, Value};
 
+use crate::archive;
+use crate::git::Revision;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Package {
-    name: String,
-    version: String,
-    authors: Vec<String>,
-    description: String,
-    license: String,
+/// The `[package]` table of vpm.toml.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub license: String,
 }
 
+/// One top module pulled in from a dependency, e.g.
+/// `{ top_module = "pfcache.v", tag = "v1.2.0" }`. At most one of `rev`, `tag`,
+/// `branch` and `version` may be set; `version` holds either a commit hash or a
+/// semver range matched against the repository's tags. Archive sources are
+/// pinned with `sha256` instead. `assets` lists extra files of the repository
+/// to copy next to the module. `params`, e.g. `{ WIDTH = 32, DEPTH = 16 }`,
+/// has vpm generate a wrapper of the module with those parameter values.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopModule {
+    pub top_module: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assets: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, ParamValue>,
+}
+
+/// A parameter value in `params`. Strings are Verilog expressions, written
+/// into the wrapper as they are, e.g. `"8'hFF"` or `"\"FAST\""`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ParamValue {
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    Expression(String),
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::Integer(value) => write!(f, "{}", value),
+            ParamValue::Real(value) => write!(f, "{:?}", value),
+            ParamValue::Boolean(value) => write!(f, "{}", u8::from(*value)),
+            ParamValue::Expression(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+const REVISION_KEYS: [&str; 5] = ["rev", "tag", "branch", "version", "sha256"];
+// Keys this version of vpm reads. Others, e.g. written by a newer vpm, are
+// kept in the file but ignored, with a warning.
+const MANIFEST_KEYS: [&str; 2] = ["package", "dependencies"];
+const PACKAGE_KEYS: [&str; 5] = ["name", "version", "authors", "description", "license"];
+const TOP_MODULE_KEYS: [&str; 8] = ["top_module", "version", "rev", "tag", "branch", "sha256", "assets", "params"];
+
+impl TopModule {
+    pub fn revision(&self) -> Revision {
+        if let Some(rev) = &self.rev {
+            Revision::Rev(rev.clone())
+        } else if let Some(tag) = &self.tag {
+            Revision::Tag(tag.clone())
+        } else if let Some(branch) = &self.branch {
+            Revision::Branch(branch.clone())
+        } else if let Some(sha256) = &self.sha256 {
+            Revision::Sha256(sha256.clone())
+        } else {
+            Revision::from_version(&self.version)
+        }
+    }
+}
+
+/// Typed view of a vpm.toml manifest.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Manifest {
+    pub package: Package,
+    /// Dependency source (repository URL) to the top modules included from it.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Vec<TopModule>>,
+}
+
+/// A vpm.toml manifest. Edits go through the underlying `DocumentMut` so that
+/// comments and formatting written by the user survive a round-trip; the typed
+/// `Manifest` is re-derived after every edit.
 #[derive(Debug)]
-struct VpmToml {
+pub struct VpmToml {
     toml_doc: DocumentMut,
+    manifest: Manifest,
+    warnings: Vec<String>,
 }
 
 impl Default for Package {
@@ -33,75 +125,117 @@ impl Default for Package {
     }
 }
 
-impl VpmToml {    
-    pub fn from(filepath: &str) -> Self {
+impl VpmToml {
+    pub fn load(filepath: &str) -> Result<Self> {
         if !Path::new(filepath).exists() {
-            let mut initial_doc = DocumentMut::new();
-            initial_doc["package"] = Item::Table(Table::new());
-            initial_doc["package"]["name"] = Item::Value(Value::from(Package::default().name));
-            initial_doc["package"]["version"] = Item::Value(Value::from(Package::default().version));
-            initial_doc["package"]["authors"] = Item::Value(Value::from(Array::from(Package::default().authors.iter().map(|s| Value::from(s.to_string())).collect())));
-            initial_doc["package"]["description"] = Item::Value(Value::from(Package::default().description));
-            initial_doc["package"]["license"] = Item::Value(Value::from(Package::default().license));
-
-            initial_doc["dependencies"] = Item::Table(Table::new());
-
-            let mut file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(filepath)
-                .expect("Failed to create vpm.toml");
-            file.write_all(initial_doc.to_string().as_bytes()).expect("Failed to write to vpm.toml");
+            let dir = std::env::current_dir().unwrap_or_default();
+            anyhow::bail!("no {} found in '{}' or any parent directory. Run `vpm init` to create one.", filepath, dir.display());
         }
 
-        let toml_content = read_to_string(filepath).expect("Failed to read vpm.toml");
-        Self {
-            toml_doc: toml_content.parse::<DocumentMut>().expect("Failed to parse vpm.toml")
-        }
+        let toml_content = fs::read_to_string(filepath)
+            .with_context(|| format!("Failed to read {}", filepath))?;
+        Self::parse(&toml_content).with_context(|| format!("Invalid manifest {}", filepath))
+    }
+
+    /// Writes a new manifest for `package` with no dependencies.
+    pub fn create(filepath: &str, package: &Package) -> Result<Self> {
+        anyhow::ensure!(!Path::new(filepath).exists(), "{} already exists", filepath);
+        let mut initial_doc = DocumentMut::new();
+        initial_doc["package"] = Item::Table(Table::new());
+        initial_doc["package"]["name"] = Item::Value(Value::from(package.name.as_str()));
+        initial_doc["package"]["version"] = Item::Value(Value::from(package.version.as_str()));
+        initial_doc["package"]["authors"] = Item::Value(Value::from(Array::from_iter(&package.authors)));
+        initial_doc["package"]["description"] = Item::Value(Value::from(package.description.as_str()));
+        initial_doc["package"]["license"] = Item::Value(Value::from(package.license.as_str()));
+
+        initial_doc["dependencies"] = Item::Table(Table::new());
+
+        let content = initial_doc.to_string();
+        fs::write(filepath, &content)
+            .with_context(|| format!("Failed to create {}", filepath))?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        // Only the immutable document keeps the spans that errors are reported with.
+        let parsed = ImDocument::parse(content).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let manifest: Manifest = toml_edit::de::from_str(content).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let warnings = validate(content, &parsed, &manifest)?;
+        Ok(Self { toml_doc: parsed.into_mut(), manifest, warnings })
     }
 
-    pub fn get_dependencies(&self) -> Option<&Table> {
-        self.toml_doc["dependencies"].as_table()
+    /// Problems that do not stop the manifest from being used, such as
+    /// unknown keys, each with its line.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
     }
 
-    pub fn add_dependency(&mut self, git: &str) {
-        self.toml_doc["dependencies"][git] = Item::Value(Value::Array(Array::new()));
+    pub fn get_dependencies(&self) -> &BTreeMap<String, Vec<TopModule>> {
+        &self.manifest.dependencies
     }
 
-    pub fn add_top_module(&mut self, repo_link: &str, module_name: &str, commit: &str) {
-        let array = self.toml_doc["dependencies"][repo_link].as_array_mut();
-        if array.is_none() {
-            self.toml_doc["dependencies"][repo_link] = Item::Value(Value::Array(Array::new()));
+    fn refresh(&mut self) -> Result<()> {
+        self.manifest = toml_edit::de::from_document(self.toml_doc.clone())
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        Ok(())
+    }
+
+    fn dependencies_table(&mut self) -> &mut Table {
+        if !self.toml_doc.contains_table("dependencies") {
+            self.toml_doc["dependencies"] = Item::Table(Table::new());
         }
-        let array = self.toml_doc["dependencies"][repo_link].as_array_mut().unwrap();
-        if !array.iter().any(|m| m.as_inline_table().unwrap().get("top_module").unwrap().as_str().unwrap() == module_name) {
-            let new_entry = Value::InlineTable({
-                let mut table = InlineTable::new();
-                table.insert("top_module".to_string(), Value::from(module_name));
-                table.insert("version".to_string(), Value::from(commit.to_string()));
-                table
-            });
-            array.push(new_entry);
+        self.toml_doc["dependencies"].as_table_mut().unwrap()
+    }
+
+    pub fn add_dependency(&mut self, git: &str) -> Result<()> {
+        let dependencies = self.dependencies_table();
+        if !dependencies.contains_key(git) {
+            dependencies[git] = Item::Value(Value::Array(Array::new()));
         }
+        self.refresh()
     }
 
-    pub fn remove_dependency(&mut self, git: &str) {
-        if let Some(dependencies) = self.toml_doc["dependencies"].as_table_mut() {
-            dependencies.remove(git);
+    pub fn add_top_module(&mut self, repo_link: &str, module_name: &str, revision: &Revision) -> Result<()> {
+        let dependencies = self.dependencies_table();
+        if dependencies.get(repo_link).and_then(Item::as_array).is_none() {
+            dependencies[repo_link] = Item::Value(Value::Array(Array::new()));
         }
+        let array = dependencies[repo_link].as_array_mut().unwrap();
+        let existing = array.iter_mut()
+            .filter_map(Value::as_inline_table_mut)
+            .find(|t| t.get("top_module").and_then(Value::as_str).map(file_name) == Some(file_name(module_name)));
+        if let Some(table) = existing {
+            for key in REVISION_KEYS {
+                table.remove(key);
+            }
+            if let Some((key, value)) = revision.manifest_field() {
+                table.insert(key, Value::from(value));
+            }
+        } else {
+            let mut table = InlineTable::new();
+            table.insert("top_module", Value::from(module_name));
+            if let Some((key, value)) = revision.manifest_field() {
+                table.insert(key, Value::from(value));
+            }
+            let mut new_entry = Value::InlineTable(table);
+            // One entry per line keeps diffs of vpm.toml readable.
+            new_entry.decor_mut().set_prefix("\n    ");
+            array.push_formatted(new_entry);
+            array.set_trailing("\n");
+            array.set_trailing_comma(true);
+        }
+        self.refresh()
     }
 
-    pub fn remove_top_module(&mut self, repo_link: &str, module_name: &str) {
-    if let Some(dependencies) = self.toml_doc["dependencies"].as_table_mut() {
-        if let Some(modules) = dependencies.get_mut(repo_link).and_then(|v| v.as_array_mut()) {
+    pub fn remove_top_module(&mut self, repo_link: &str, module_name: &str) -> Result<()> {
+        let dependencies = self.dependencies_table();
+        if let Some(modules) = dependencies.get_mut(repo_link).and_then(Item::as_array_mut) {
             modules.retain(|m| {
-                if let Some(table) = m.as_inline_table() {
-                    if let Some(top_module) = table.get("top_module").and_then(|v| v.as_str()) {
-                        return top_module != module_name;
-                    }
-                }
-                true
+                m.as_inline_table()
+                    .and_then(|t| t.get("top_module"))
+                    .and_then(Value::as_str)
+                    .map(file_name)
+                    != Some(file_name(module_name))
             });
 
             // If the array is empty after removal, remove the entire dependency
@@ -109,87 +243,240 @@ impl VpmToml {
                 dependencies.remove(repo_link);
             }
         }
-    }
+        self.refresh()
     }
 
     pub fn write_to_file(&self, filepath: &str) -> Result<()> {
-        let toml_content = self.toml_doc.to_string();
-        let mut formatted_content = String::new();
-        for line in toml_content.lines() {
-            if !line.trim().contains("}, ") {
-                formatted_content.push_str(line);
-            } else {
-                let indent_level = line.chars().take_while(|&c| c != '{').count();
-                formatted_content.push_str(&line.replace("}, ", &format!("}},\n{}", " ".repeat(indent_level))));
-            }
-            formatted_content.push('\n');
-        }
-
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(filepath)
-            .expect("Failed to open vpm.toml");
-        file.write_all(formatted_content.as_bytes()).expect("Failed to write to vpm.toml");
+        fs::write(filepath, self.toml_doc.to_string())
+            .with_context(|| format!("Failed to write {}", filepath))?;
         Ok(())
     }
 
     pub fn get_repo_links(&self, module_name: &str) -> HashSet<String> {
-        let module_file_name = module_name.split('/').last().unwrap();
-        let mut repo_links = HashSet::new();
-        if let Some(dependencies) = self.toml_doc.get("dependencies") {
-            for (repo_link, dependency) in dependencies.as_table().unwrap().iter() {
-                for module_table in dependency.as_array().unwrap().iter() {
-                    let top_module = module_table.as_inline_table().unwrap().get("top_module").unwrap().as_str().unwrap().split('/').last().unwrap();
-                    if top_module == module_file_name {
-                        repo_links.insert(repo_link.to_string());
-                    }
+        let module_file_name = file_name(module_name);
+        self.manifest.dependencies.iter()
+            .filter(|(_, modules)| modules.iter().any(|m| file_name(&m.top_module) == module_file_name))
+            .map(|(repo_link, _)| repo_link.to_string())
+            .collect()
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Checks the invariants serde cannot express, reporting the offending line.
+/// Returns the warnings about keys that are ignored.
+fn validate(content: &str, doc: &ImDocument<&str>, manifest: &Manifest) -> Result<Vec<String>> {
+    let warnings = unknown_keys(content, doc);
+    let package = doc["package"].as_table();
+    let span_of = |key: &str| package.and_then(|t| t.get(key)).and_then(Item::span);
+    if manifest.package.name.trim().is_empty() {
+        return Err(manifest_error(content, span_of("name"), "package name must not be empty"));
+    }
+    if manifest.package.version.trim().is_empty() {
+        return Err(manifest_error(content, span_of("version"), "package version must not be empty"));
+    }
+
+    let Some(dependencies) = doc.get("dependencies").and_then(Item::as_table_like) else {
+        return Ok(warnings);
+    };
+    for (repo_link, modules) in &manifest.dependencies {
+        let key_span = dependencies.get_key_value(repo_link).and_then(|(k, _)| k.span());
+        if repo_link.trim().is_empty() {
+            return Err(manifest_error(content, key_span, "dependency source must not be empty"));
+        }
+        let values = dependencies.get(repo_link).and_then(Item::as_array);
+        let mut seen = HashSet::new();
+        for (i, module) in modules.iter().enumerate() {
+            let span = values.and_then(|a| a.get(i)).and_then(Value::span).or(key_span.clone());
+            if module.top_module.trim().is_empty() {
+                return Err(manifest_error(content, span, &format!("empty `top_module` in dependency '{}'", repo_link)));
+            }
+            if !seen.insert(file_name(&module.top_module)) {
+                return Err(manifest_error(content, span, &format!("top module '{}' is listed twice for dependency '{}'", module.top_module, repo_link)));
+            }
+            for asset in &module.assets {
+                let path = Path::new(asset);
+                if asset.trim().is_empty() || path.is_absolute() || path.components().any(|c| c == std::path::Component::ParentDir) {
+                    return Err(manifest_error(content, span, &format!("asset '{}' of top module '{}' must be a relative path inside the repository", asset, module.top_module)));
+                }
+            }
+            for (name, value) in &module.params {
+                if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(manifest_error(content, span, &format!("parameter '{}' of top module '{}' is not a Verilog identifier", name, module.top_module)));
+                }
+                if matches!(value, ParamValue::Expression(expression) if expression.trim().is_empty()) {
+                    return Err(manifest_error(content, span, &format!("parameter '{}' of top module '{}' has an empty value", name, module.top_module)));
+                }
+            }
+            let revisions = [module.rev.is_some(), module.tag.is_some(), module.branch.is_some(), !module.version.is_empty(), module.sha256.is_some()];
+            if revisions.iter().filter(|&&set| set).count() > 1 {
+                return Err(manifest_error(content, span, &format!("top module '{}' may only set one of {}", module.top_module, REVISION_KEYS.join(", "))));
+            }
+            if archive::is_archive(repo_link) {
+                match &module.sha256 {
+                    Some(sha256) if archive::is_sha256(sha256) => {}
+                    Some(sha256) => return Err(manifest_error(content, span, &format!("'{}' is not a SHA-256 hash", sha256))),
+                    None => return Err(manifest_error(content, span, &format!("top module '{}' comes from an archive and must set `sha256`", module.top_module))),
                 }
+            } else if module.sha256.is_some() {
+                return Err(manifest_error(content, span, &format!("`sha256` only applies to archive sources, but '{}' is a repository", repo_link)));
             }
         }
-        repo_links
+    }
+    Ok(warnings)
+}
+
+/// Keys of `doc` that this version of vpm does not know, as messages.
+fn unknown_keys(content: &str, doc: &ImDocument<&str>) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let mut check = |table: &dyn toml_edit::TableLike, known: &[&str], location: &str| {
+        for (key, _) in table.iter().filter(|(key, _)| !known.contains(key)) {
+            let span = table.get_key_value(key).and_then(|(k, _)| k.span());
+            unknown.push(manifest_error(content, span, &format!("unknown key '{}' in {} is ignored", key, location)).to_string());
+        }
+    };
+    check(doc.as_table(), &MANIFEST_KEYS, "vpm.toml");
+    if let Some(package) = doc.get("package").and_then(Item::as_table_like) {
+        check(package, &PACKAGE_KEYS, "[package]");
+    }
+    for (repo_link, modules) in doc.get("dependencies").and_then(Item::as_table_like).into_iter().flat_map(|d| d.iter()) {
+        for module in modules.as_array().into_iter().flatten().filter_map(Value::as_inline_table) {
+            check(module, &TOP_MODULE_KEYS, &format!("a top module of '{}'", repo_link));
+        }
+    }
+    unknown
+}
+
+fn manifest_error(content: &str, span: Option<Range<usize>>, message: &str) -> anyhow::Error {
+    match span {
+        Some(span) => {
+            let line = content[..span.start.min(content.len())].matches('\n').count() + 1;
+            anyhow::anyhow!("line {}: {}", line, message)
+        }
+        None => anyhow::anyhow!("{}", message),
     }
 }
 
 pub fn add_dependency(git: &str) -> Result<()> {
-    let mut vpm_toml = VpmToml::from("vpm.toml");
-    if !vpm_toml.get_dependencies().unwrap().contains_key(git) {
-        vpm_toml.add_dependency(git);
+    let mut vpm_toml = VpmToml::load("vpm.toml")?;
+    if !vpm_toml.get_dependencies().contains_key(git) {
+        vpm_toml.add_dependency(git)?;
         vpm_toml.write_to_file("vpm.toml")?;
     }
     Ok(())
 }
 
-pub fn add_top_module(repo_link: &str, module_path: &str, commit: &str) -> Result<()> {
-    let mut vpm_toml = VpmToml::from("vpm.toml");
-    vpm_toml.add_top_module(repo_link, module_path, commit);
+pub fn add_top_module(repo_link: &str, module_path: &str, revision: &Revision) -> Result<()> {
+    let mut vpm_toml = VpmToml::load("vpm.toml")?;
+    vpm_toml.add_top_module(repo_link, module_path, revision)?;
     vpm_toml.write_to_file("vpm.toml")?;
     Ok(())
 }
 
-fn remove_dependency(git: &str) -> Result<()> {
-    let mut vpm_toml = VpmToml::from("vpm.toml");
-    vpm_toml.remove_dependency(git);
+pub fn remove_top_module(repo_link: &str, module_name: &str) -> Result<()> {
+    let mut vpm_toml = VpmToml::load("vpm.toml")?;
+    vpm_toml.remove_top_module(repo_link, module_name)?;
     vpm_toml.write_to_file("vpm.toml")?;
     Ok(())
 }
 
-pub fn remove_top_module(repo_link: &str, module_name: &str) -> Result<()> {
-    let mut vpm_toml = VpmToml::from("vpm.toml");
-    vpm_toml.remove_top_module(repo_link, module_name);
-    if let Some(dependencies) = vpm_toml.toml_doc["dependencies"].as_table() {
-        if let Some(modules) = dependencies.get(repo_link).and_then(|v| v.as_array()) {
-            if modules.is_empty() {
-                remove_dependency(repo_link)?;
-            }
-        }
-    }
-    vpm_toml.write_to_file("vpm.toml")?;
-    Ok(())
+/// Extra assets listed for `module_name` of `repo_link`.
+pub fn get_assets(repo_link: &str, module_name: &str) -> Result<Vec<String>> {
+    let vpm_toml = VpmToml::load("vpm.toml")?;
+    Ok(vpm_toml.get_dependencies().get(repo_link).into_iter().flatten()
+        .find(|m| file_name(&m.top_module) == file_name(module_name))
+        .map(|m| m.assets.clone())
+        .unwrap_or_default())
+}
+
+pub fn get_repo_links(module_name: &str) -> Result<HashSet<String>> {
+    let vpm_toml = VpmToml::load("vpm.toml")?;
+    Ok(vpm_toml.get_repo_links(module_name))
 }
 
-pub fn get_repo_links(module_name: &str) -> HashSet<String> {
-    let vpm_toml = VpmToml::from("vpm.toml");
-    vpm_toml.get_repo_links(module_name)
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKAGE: &str = "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[dependencies]\n";
+
+    fn error(dependencies: &str) -> String {
+        VpmToml::parse(&format!("{}{}", PACKAGE, dependencies)).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_a_valid_manifest() {
+        let manifest = VpmToml::parse(&format!(
+            "{}# Pinned for the FIFO fix.\n\"https://github.com/ZipCPU/zipcpu\" = [\n    {{ top_module = \"fifo.sv\", tag = \"v1.0\", params = {{ WIDTH = 32, DEPTH = 16 }} }},\n]\n",
+            PACKAGE
+        )).unwrap();
+        let modules = &manifest.get_dependencies()["https://github.com/ZipCPU/zipcpu"];
+        assert_eq!(modules[0].revision(), Revision::Tag("v1.0".to_string()));
+        assert_eq!(modules[0].params["WIDTH"], ParamValue::Integer(32));
+        assert!(manifest.warnings().is_empty());
+        assert!(manifest.toml_doc.to_string().contains("# Pinned for the FIFO fix."));
+    }
+
+    #[test]
+    fn ignores_unknown_keys_with_a_warning() {
+        let content = format!(
+            "{}\"https://github.com/a/b\" = [{{ top_module = \"a.v\", features = [\"x\"] }}]\n\n[workspace]\nmembers = []\n",
+            PACKAGE.replace("version = \"0.1.0\"\n", "version = \"0.1.0\"\nedition = \"2025\"\n")
+        );
+        let manifest = VpmToml::parse(&content).unwrap();
+        assert_eq!(manifest.get_dependencies()["https://github.com/a/b"][0].top_module, "a.v");
+        assert_eq!(manifest.warnings(), [
+            "line 9: unknown key 'workspace' in vpm.toml is ignored",
+            "line 4: unknown key 'edition' in [package] is ignored",
+            "line 7: unknown key 'features' in a top module of 'https://github.com/a/b' is ignored",
+        ]);
+    }
+
+    #[test]
+    fn replaces_the_revision_of_a_top_module_in_place() {
+        let mut manifest = VpmToml::parse(&format!(
+            "{}\"https://github.com/a/b\" = [{{ top_module = \"rtl/fifo.v\", tag = \"v1\", assets = [\"rom.hex\"], params = {{ W = 8 }} }}]\n",
+            PACKAGE
+        )).unwrap();
+        manifest.add_top_module("https://github.com/a/b", "vpm_modules/fifo/rtl/fifo.v", &Revision::Rev("0123abcd".to_string())).unwrap();
+        let modules = &manifest.get_dependencies()["https://github.com/a/b"];
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].revision(), Revision::Rev("0123abcd".to_string()));
+        assert_eq!(modules[0].assets, ["rom.hex"]);
+        assert_eq!(modules[0].params["W"], ParamValue::Integer(8));
+    }
+
+    #[test]
+    fn reports_the_line_of_invalid_entries() {
+        assert_eq!(
+            VpmToml::parse("[package]\nname = \" \"\nversion = \"0.1.0\"\n").unwrap_err().to_string(),
+            "line 2: package name must not be empty"
+        );
+        assert_eq!(
+            error("\"https://github.com/a/b\" = [\n    { top_module = \"a.v\" },\n    { top_module = \"rtl/a.v\" },\n]\n"),
+            "line 8: top module 'rtl/a.v' is listed twice for dependency 'https://github.com/a/b'"
+        );
+        assert_eq!(
+            error("\"https://github.com/a/b\" = [{ top_module = \" \" }]\n"),
+            "line 6: empty `top_module` in dependency 'https://github.com/a/b'"
+        );
+        assert_eq!(
+            error("\"https://github.com/a/b\" = [{ top_module = \"a.v\", tag = \"v1\", branch = \"main\" }]\n"),
+            "line 6: top module 'a.v' may only set one of rev, tag, branch, version, sha256"
+        );
+        assert_eq!(
+            error("\"https://example.com/ip.tar.gz\" = [{ top_module = \"a.v\" }]\n"),
+            "line 6: top module 'a.v' comes from an archive and must set `sha256`"
+        );
+        assert_eq!(
+            error("\"https://github.com/a/b\" = [{ top_module = \"a.v\", assets = [\"../secret\"] }]\n"),
+            "line 6: asset '../secret' of top module 'a.v' must be a relative path inside the repository"
+        );
+        assert_eq!(
+            error("\"https://github.com/a/b\" = [{ top_module = \"a.v\", params = { \"1W\" = 8 } }]\n"),
+            "line 6: parameter '1W' of top module 'a.v' is not a Verilog identifier"
+        );
+    }
+}
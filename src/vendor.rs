@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::archive;
+use crate::cache::{self, cache_dir};
+use crate::git::{self, Revision};
+use crate::lockfile::Lockfile;
+
+const INDEX_FILE: &str = "vpm-vendor.toml";
+
+/// Index of a vendor bundle. The same format records, in the cache, every
+/// source imported from a bundle.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct VendorIndex {
+    #[serde(default, rename = "source")]
+    pub sources: Vec<VendoredSource>,
+}
+
+/// One upstream source in a vendor bundle.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VendoredSource {
+    pub source: String,
+    /// Commits of `source` recorded in vpm.lock; SHA-256 hashes for archives.
+    pub commits: Vec<String>,
+    /// What HEAD of `source` resolved to when the bundle was exported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+    /// The git bundle or archives of `source`, relative to the bundle root.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+}
+
+fn imported_index() -> Result<PathBuf> {
+    Ok(cache_dir()?.join("vendored.toml"))
+}
+
+fn load(path: &Path) -> Result<VendorIndex> {
+    if !path.exists() {
+        return Ok(VendorIndex::default());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    toml_edit::de::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| format!("Invalid vendor index {}", path.display()))
+}
+
+fn save(index: &VendorIndex, path: &Path) -> Result<()> {
+    let content = toml_edit::ser::to_string_pretty(index)?;
+    fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The imported vendor entry of `source`, if a bundle provided it.
+pub fn vendored(source: &str) -> Option<VendoredSource> {
+    let index = load(&imported_index().ok()?).ok()?;
+    index.sources.into_iter().find(|s| s.source == source)
+}
+
+/// The cache mirror that stands in for the git repository at `source` once
+/// it was imported from a bundle.
+pub fn vendored_mirror(source: &str) -> Option<String> {
+    if archive::is_archive(source) {
+        return None;
+    }
+    vendored(source)?;
+    cache::mirror(source).ok().map(|path| path.to_string_lossy().to_string())
+}
+
+fn tar(args: &[&str]) -> Result<()> {
+    let output = Command::new("tar").args(args).output()
+        .context("Failed to execute tar. Ensure tar is installed and accessible from the command line.")?;
+    anyhow::ensure!(output.status.success(), "`tar {}` failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    Ok(())
+}
+
+/// Packs every upstream revision recorded in vpm.lock into the tar.gz `output`:
+/// one git bundle per repository, the archives of archive sources, and an index.
+pub fn export(output: &Path) -> Result<()> {
+    let lockfile = Lockfile::load("vpm.lock")?;
+    let mut sources: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for entry in lockfile.packages.iter().filter(|e| e.kind.is_copied()) {
+        sources.entry(entry.source.clone()).or_default().insert(entry.commit.clone());
+    }
+    anyhow::ensure!(!sources.is_empty(), "vpm.lock records no dependencies. Include or fetch them before exporting.");
+
+    let staging = tempfile::tempdir()?;
+    let mut index = VendorIndex::default();
+    for (source, commits) in sources {
+        if git::is_plain_directory(&source) {
+            println!("Skipping '{}': local directories are not vendored", source);
+            continue;
+        }
+        let commits: Vec<String> = commits.into_iter().collect();
+        let mut files = Vec::new();
+        let head = if archive::is_archive(&source) {
+            fs::create_dir_all(staging.path().join("archives"))?;
+            for sha256 in &commits {
+                let cached = archive::cached(&source, sha256)?;
+                let file = format!("archives/{}", cached.file_name().unwrap_or_default().to_string_lossy());
+                fs::copy(&cached, staging.path().join(&file))?;
+                files.push(file);
+            }
+            commits.last().cloned()
+        } else {
+            // Recorded so that includes without a revision resolve on the offline machine too.
+            let head = git::resolve_revision(&source, &Revision::Head)
+                .with_context(|| format!("Failed to resolve HEAD of '{}' for the bundle", source))?;
+            let head = Some(head);
+            for commit in commits.iter().chain(&head) {
+                cache::fetch_commit(&source, commit)?;
+            }
+            fs::create_dir_all(staging.path().join("git"))?;
+            let file = format!("git/{}.bundle", cache::repo_key(&source));
+            let bundle = staging.path().join(&file);
+            git::run_in(&cache::mirror(&source)?, &["bundle", "create", &bundle.to_string_lossy(), "--all"])
+                .with_context(|| format!("Failed to bundle '{}'", source))?;
+            files.push(file);
+            head
+        };
+        println!("Vendored {} ({} revision(s))", source, commits.len());
+        index.sources.push(VendoredSource { source, commits, head, files });
+    }
+    save(&index, &staging.path().join(INDEX_FILE))?;
+
+    let output = std::env::current_dir()?.join(output);
+    tar(&["-czf", &output.to_string_lossy(), "-C", &staging.path().to_string_lossy(), "."])?;
+    println!("Exported {} source(s) to {}", index.sources.len(), output.display());
+    Ok(())
+}
+
+/// Unpacks a bundle written by [`export`] into the cache. From then on the
+/// sources it contains resolve and fetch from the cache alone.
+pub fn import(bundle: &Path) -> Result<()> {
+    let staging = tempfile::tempdir()?;
+    tar(&["-xzf", &bundle.to_string_lossy(), "-C", &staging.path().to_string_lossy()])?;
+    let index_path = staging.path().join(INDEX_FILE);
+    anyhow::ensure!(index_path.exists(), "'{}' is not a vpm vendor bundle: it has no {}", bundle.display(), INDEX_FILE);
+    let index = load(&index_path)?;
+
+    let imported_path = imported_index()?;
+    let mut imported = load(&imported_path)?;
+    for source in index.sources {
+        if archive::is_archive(&source.source) {
+            for file in &source.files {
+                let bytes = fs::read(staging.path().join(file))
+                    .with_context(|| format!("The bundle is missing {}", file))?;
+                archive::store(&source.source, &bytes)?;
+            }
+        } else {
+            let mirror = cache::mirror(&source.source)?;
+            for file in &source.files {
+                let bundle_file = staging.path().join(file);
+                git::run_in(&mirror, &["fetch", "--quiet", &bundle_file.to_string_lossy(), "+refs/*:refs/*"])
+                    .with_context(|| format!("Failed to import '{}'", source.source))?;
+            }
+            if let Some(head) = &source.head {
+                // Lets HEAD resolve against the mirror as it did against the upstream repository.
+                git::run_in(&mirror, &["update-ref", "--no-deref", "HEAD", head])?;
+            }
+        }
+        let missing: Vec<&String> = source.commits.iter()
+            .filter(|commit| !archive::is_archive(&source.source) && !cache::has_commit(&source.source, commit))
+            .collect();
+        anyhow::ensure!(missing.is_empty(), "The bundle does not contain {} of '{}'", missing.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", "), source.source);
+
+        println!("Imported {} ({} revision(s))", source.source, source.commits.len());
+        imported.sources.retain(|s| s.source != source.source);
+        imported.sources.push(VendoredSource { files: Vec::new(), ..source });
+    }
+    fs::create_dir_all(cache_dir()?)?;
+    save(&imported, &imported_path)?;
+    println!("vpm now resolves these sources from {} without network access.", cache_dir()?.display());
+    Ok(())
+}
+
+/// Undoes every import: the sources are fetched from upstream again. The
+/// revisions imported stay in their cache mirrors.
+pub fn forget() -> Result<()> {
+    let path = imported_index()?;
+    let imported = load(&path)?;
+    if imported.sources.is_empty() {
+        println!("No vendored sources are imported.");
+        return Ok(());
+    }
+    fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    for source in &imported.sources {
+        println!("Forgot {}", source.source);
+    }
+    println!("vpm fetches these sources from upstream again.");
+    Ok(())
+}